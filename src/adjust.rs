@@ -0,0 +1,343 @@
+// heic-to-dynamic-gnome-wallpaper
+// Copyright (C) 2022 Johannes Wünsche
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::error::Error;
+use crate::image::Slot;
+use crate::util::time;
+use crate::DAY_SECS;
+use anyhow::Result;
+use chrono::{NaiveTime, Timelike};
+use std::cmp::Ordering;
+
+/// Images of time-based wallpapers shown from this time of day until
+/// [`DAY_END`] count as daylight images, as these wallpapers do not mark them.
+const DAY_START: f32 = 0.25;
+const DAY_END: f32 = 0.75;
+
+/// An image shown at a fixed time of day, the rest of the schedule is shifted
+/// along with it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Anchor {
+    /// Index of the image in the container
+    pub image: usize,
+    /// Time of day as fraction of the day
+    pub time: f32,
+}
+
+impl Anchor {
+    /// Parse an anchor given as `IMAGE@HH:MM`, e.g. `5@12:00`.
+    pub fn parse(anchor: &str) -> Result<Self> {
+        let invalid = || {
+            Error::InvalidArgument(format!(
+                "Invalid anchor \"{}\", expected IMAGE@HH:MM like 5@12:00",
+                anchor
+            ))
+        };
+        let (image, time) = anchor.trim().split_once('@').ok_or_else(invalid)?;
+        let image = image.parse().map_err(|_| invalid())?;
+        let time = NaiveTime::parse_from_str(time, "%H:%M:%S")
+            .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M"))
+            .map_err(|_| invalid())?;
+        Ok(Self {
+            image,
+            time: time.num_seconds_from_midnight() as f32 / DAY_SECS,
+        })
+    }
+}
+
+/// Changes to the times at which the images of time-based and solar
+/// wallpapers are shown, e.g. to follow a day shifted by night shifts. They
+/// are applied in the order of the fields.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Adjustment {
+    /// Factor the part of the day shown with daylight images is stretched by,
+    /// the night images share the remaining time. Daylight images are those
+    /// of the light style, for time-based wallpapers those shown between
+    /// 06:00 and 18:00.
+    pub day_stretch: Option<f32>,
+    /// Image shown at a fixed time of day.
+    pub anchor: Option<Anchor>,
+    /// Shift of the whole schedule as fraction of the day.
+    pub offset: f32,
+}
+
+impl Adjustment {
+    /// Parse a shift given as signed duration like `+3h`, `-1h30m` or `45m`.
+    pub fn parse_offset(offset: &str) -> Result<f32> {
+        let invalid = || {
+            Error::InvalidArgument(format!(
+                "Invalid offset \"{}\", expected a duration like +3h, -1h30m or 45m",
+                offset
+            ))
+        };
+        let trimmed = offset.trim();
+        let (sign, mut rest) = match trimmed.strip_prefix('-') {
+            Some(rest) => (-1.0, rest),
+            None => (1.0, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+        };
+        if rest.is_empty() {
+            return Err(invalid().into());
+        }
+        let mut seconds = 0.0;
+        while !rest.is_empty() {
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .ok_or_else(invalid)?;
+            let value: f32 = rest[..digits].parse().map_err(|_| invalid())?;
+            let unit = match rest[digits..].chars().next() {
+                Some('h') => 3600.0,
+                Some('m') => 60.0,
+                Some('s') => 1.0,
+                _ => return Err(invalid().into()),
+            };
+            seconds += value * unit;
+            rest = &rest[digits + 1..];
+        }
+        Ok(sign * seconds / DAY_SECS)
+    }
+
+    /// Change the times of slots sorted by their time of day, which are sorted
+    /// again afterwards.
+    pub fn apply(&self, slots: &mut [Slot]) -> Result<()> {
+        if *self == Adjustment::default() {
+            return Ok(());
+        }
+        if let Some(factor) = self.day_stretch {
+            stretch_day(slots, factor)?;
+        }
+        let mut shift = self.offset;
+        if let Some(anchor) = self.anchor {
+            let slot = slots
+                .iter()
+                .find(|slot| slot.image == anchor.image)
+                .ok_or_else(|| {
+                    Error::InvalidArgument(format!(
+                        "Image {} is not part of the schedule and cannot be anchored",
+                        anchor.image
+                    ))
+                })?;
+            shift += anchor.time - slot.time;
+        }
+        for slot in slots.iter_mut() {
+            slot.time = time::wrap_time_of_day(slot.time + shift);
+        }
+        slots.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(Ordering::Equal));
+        Ok(())
+    }
+}
+
+/// Stretch the time between the first daylight image and the first night
+/// image following the daylight images around its middle, and compress the
+/// night accordingly.
+fn stretch_day(slots: &mut [Slot], factor: f32) -> Result<()> {
+    let count = slots.len();
+    let marked = slots.iter().any(|slot| slot.dark);
+    let day: Vec<bool> = slots
+        .iter()
+        .map(|slot| {
+            if marked {
+                !slot.dark
+            } else {
+                (DAY_START..DAY_END).contains(&slot.time)
+            }
+        })
+        .collect();
+    // Daylight images following a night image
+    let sunrises: Vec<usize> = (0..count)
+        .filter(|&idx| day[idx] && !day[(idx + count - 1) % count])
+        .collect();
+    let first = match sunrises.as_slice() {
+        [first] => *first,
+        [] => return Err(cannot_stretch("it lacks night or daylight images")),
+        _ => return Err(cannot_stretch("its images are interrupted by night images")),
+    };
+    let last = (first + day.iter().filter(|day| **day).count()) % count;
+
+    let start = slots[first].time;
+    let end = slots[last].time;
+    let length = (end - start).rem_euclid(1.0);
+    let stretched = length * factor;
+    if stretched <= 0.0 || stretched >= 1.0 {
+        return Err(Error::InvalidArgument(format!(
+            "Stretching the day by {} leaves no time for the {}",
+            factor,
+            if stretched <= 0.0 { "day" } else { "night" }
+        ))
+        .into());
+    }
+    let new_start = start + (length - stretched) / 2.0;
+    let new_end = new_start + stretched;
+    for (slot, day) in slots.iter_mut().zip(day) {
+        slot.time = time::wrap_time_of_day(if day {
+            new_start + (slot.time - start).rem_euclid(1.0) * stretched / length
+        } else {
+            new_end + (slot.time - end).rem_euclid(1.0) * (1.0 - stretched) / (1.0 - length)
+        });
+    }
+    Ok(())
+}
+
+fn cannot_stretch(reason: &str) -> anyhow::Error {
+    Error::InvalidArgument(format!("The day cannot be stretched, {}", reason)).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image;
+    use crate::schema::xml::{File, Image};
+    use chrono::NaiveDate;
+    use std::collections::BTreeMap;
+
+    const HOUR: f32 = 3600.0 / DAY_SECS;
+
+    fn slots(times: &[(f32, bool)]) -> Vec<Slot> {
+        times
+            .iter()
+            .enumerate()
+            .map(|(image, &(time, dark))| Slot { time, image, dark })
+            .collect()
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    /// Seconds the slots are shown for in the GNOME schedule
+    fn total_duration(slots: &[Slot]) -> f32 {
+        let files: BTreeMap<usize, File> = slots
+            .iter()
+            .map(|slot| (slot.image, File::Single(format!("{}.png", slot.image))))
+            .collect();
+        let date = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        image::plan_background(slots, &files, date)
+            .images
+            .iter()
+            .map(|image| match image {
+                Image::Static { duration, .. } | Image::Transition { duration, .. } => duration,
+            })
+            .sum()
+    }
+
+    #[test]
+    fn offsets() {
+        assert_close(Adjustment::parse_offset("+3h").unwrap(), 3.0 * HOUR);
+        assert_close(Adjustment::parse_offset("3h").unwrap(), 3.0 * HOUR);
+        assert_close(Adjustment::parse_offset("-90m").unwrap(), -1.5 * HOUR);
+        assert_close(Adjustment::parse_offset("-1h30m").unwrap(), -1.5 * HOUR);
+        assert_close(Adjustment::parse_offset("45m30s").unwrap(), 0.7583 * HOUR);
+        for invalid in ["", "+", "3", "3x", "h", "1h-2m"] {
+            assert!(Adjustment::parse_offset(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn anchors() {
+        let anchor = Anchor::parse("5@12:00").unwrap();
+        assert_eq!(anchor.image, 5);
+        assert_close(anchor.time, 0.5);
+        assert_close(Anchor::parse("0@06:00:36").unwrap().time, 6.01 * HOUR);
+        for invalid in ["5", "12:00", "x@12:00", "5@25:00", "5@noon"] {
+            assert!(Anchor::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn offset_wraps_around() {
+        let mut wrapped = slots(&[(0.0, true), (0.5, false), (0.9, true)]);
+        Adjustment {
+            offset: 3.0 * HOUR,
+            ..Default::default()
+        }
+        .apply(&mut wrapped)
+        .unwrap();
+        let images: Vec<usize> = wrapped.iter().map(|slot| slot.image).collect();
+        assert_eq!(images, [2, 0, 1]);
+        assert_close(wrapped[0].time, 0.9 + 3.0 * HOUR - 1.0);
+
+        // A whole day backwards ends up where it started, never at 1.0
+        let mut wrapped = slots(&[(0.0, true), (0.5, false)]);
+        Adjustment {
+            offset: -1.0,
+            ..Default::default()
+        }
+        .apply(&mut wrapped)
+        .unwrap();
+        assert!(wrapped.iter().all(|slot| (0.0..1.0).contains(&slot.time)));
+        assert_close(wrapped[0].time, 0.0);
+    }
+
+    #[test]
+    fn anchor_shifts_schedule() {
+        let mut anchored = slots(&[(0.0, true), (0.25, false), (0.5, false)]);
+        Adjustment {
+            anchor: Some(Anchor::parse("1@09:00").unwrap()),
+            ..Default::default()
+        }
+        .apply(&mut anchored)
+        .unwrap();
+        assert_close(anchored[0].time, 3.0 * HOUR);
+        assert_close(anchored[1].time, 9.0 * HOUR);
+        assert_close(anchored[2].time, 15.0 * HOUR);
+        assert!(Adjustment {
+            anchor: Some(Anchor::parse("7@09:00").unwrap()),
+            ..Default::default()
+        }
+        .apply(&mut anchored)
+        .is_err());
+    }
+
+    #[test]
+    fn stretch_keeps_a_day() {
+        let times = [(0.0, true), (0.25, false), (0.5, false), (0.75, true)];
+        for factor in [0.5, 1.2, 1.9] {
+            let mut stretched = slots(&times);
+            Adjustment {
+                day_stretch: Some(factor),
+                ..Default::default()
+            }
+            .apply(&mut stretched)
+            .unwrap();
+            // The day runs from the first daylight image to the first night
+            // image after it
+            let sunrise = stretched.iter().find(|slot| slot.image == 1).unwrap();
+            let sunset = stretched.iter().find(|slot| slot.image == 3).unwrap();
+            assert_close((sunset.time - sunrise.time).rem_euclid(1.0), 0.5 * factor);
+            assert_close(total_duration(&stretched), DAY_SECS);
+        }
+        for factor in [0.0, 2.0] {
+            let mut stretched = slots(&times);
+            assert!(Adjustment {
+                day_stretch: Some(factor),
+                ..Default::default()
+            }
+            .apply(&mut stretched)
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn late_start() {
+        let late = slots(&[(20.0 * HOUR, true), (22.5 * HOUR, true)]);
+        let date = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        let files = BTreeMap::from([
+            (0, File::Single("0.png".to_string())),
+            (1, File::Single("1.png".to_string())),
+        ]);
+        let starttime = image::plan_background(&late, &files, date).starttime;
+        assert_eq!((starttime.hour, starttime.minute), (20, 0));
+        assert_close(total_duration(&late), DAY_SECS);
+    }
+}
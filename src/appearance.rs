@@ -0,0 +1,73 @@
+// heic-to-dynamic-gnome-wallpaper
+// Copyright (C) 2022 Johannes Wünsche
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::image::{process_images, save_properties, Output};
+use crate::progress::{Progress, Stage};
+use crate::schema::plist::WallpaperMetaAppearance;
+use crate::schema::xml::{File, Wallpaper, Wallpapers};
+use crate::util::path;
+use crate::{DynamicWallpaper, Options};
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub fn export(
+    wallpaper: &DynamicWallpaper,
+    plist: &WallpaperMetaAppearance,
+    options: &Options,
+    parent_directory: &Path,
+    image_name: &str,
+    progress: &dyn Progress,
+) -> Result<Output> {
+    if !options.sizes.is_empty() {
+        progress.warning(
+            "Appearance wallpapers refer to single images, the images keep their original size.",
+        );
+    }
+    let extension = options.encoding.format.extension();
+    let light = path::file_in(parent_directory, &format!("{}.{}", plist.light, extension))?;
+    // Both styles may use the same image
+    let dark = if plist.dark == plist.light {
+        light.clone()
+    } else {
+        path::file_in(parent_directory, &format!("{}.{}", plist.dark, extension))?
+    };
+
+    progress.message(
+        Stage::Conversion,
+        &format!("Converting light and dark image to {} format...", extension),
+    );
+    let mut files = BTreeMap::new();
+    files.insert(plist.light, File::Single(light.clone()));
+    files.insert(plist.dark, File::Single(dark.clone()));
+    process_images(wallpaper, &files, options, parent_directory, progress)?;
+
+    let properties = Wallpapers {
+        wallpapers: vec![Wallpaper {
+            name: image_name.to_string(),
+            filename: light.clone(),
+            filename_dark: Some(dark.clone()),
+            options: "zoom".to_string(),
+            shade_type: "solid".to_string(),
+        }],
+    };
+    save_properties(&properties, parent_directory, image_name, progress)?;
+    Ok(Output {
+        images: vec![light.clone(), dark.clone()],
+        picture: light,
+        picture_dark: Some(dark),
+        schedule: Vec::new(),
+    })
+}
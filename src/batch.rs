@@ -0,0 +1,145 @@
+// heic-to-dynamic-gnome-wallpaper
+// Copyright (C) 2022 Johannes Wünsche
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::error::Error;
+use crate::Summary;
+use anyhow::Result;
+use colored::*;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// All heic images in the directory and its subdirectories, sorted by path.
+/// Symbolic links to directories are not followed.
+pub fn find_wallpapers(directory: &Path) -> Result<Vec<PathBuf>> {
+    let mut wallpapers = Vec::new();
+    let mut directories = vec![directory.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        let entries = std::fs::read_dir(&directory).map_err(|source| Error::Io {
+            path: directory.clone(),
+            source,
+        })?;
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                directories.push(path);
+            } else if is_heic(&path) {
+                wallpapers.push(path);
+            }
+        }
+    }
+    wallpapers.sort();
+    Ok(wallpapers)
+}
+
+/// Names of the wallpapers of a batch, which are their file names without
+/// extension. Wallpapers sharing a file name, e.g. from different
+/// directories, are told apart by a number like `Mojave-2` so that they do not
+/// overwrite each other. `None` for paths without valid file name.
+pub fn unique_names(paths: &[PathBuf]) -> Vec<Option<String>> {
+    let mut used = BTreeSet::new();
+    paths
+        .iter()
+        .map(|path| {
+            let stem = path.file_stem()?.to_str()?;
+            let mut name = stem.to_string();
+            let mut number = 1;
+            while !used.insert(name.clone()) {
+                number += 1;
+                name = format!("{}-{}", stem, number);
+            }
+            Some(name)
+        })
+        .collect()
+}
+
+fn is_heic(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| ["heic", "heif"].contains(&extension.to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Outcome of converting one wallpaper of a batch as printed with
+/// `--output json`
+#[derive(Serialize)]
+pub struct Entry<'a> {
+    file: &'a Path,
+    #[serde(flatten)]
+    summary: Option<&'a Summary>,
+    error: Option<String>,
+}
+
+impl<'a> From<&'a (PathBuf, Result<Summary>)> for Entry<'a> {
+    fn from((file, result): &'a (PathBuf, Result<Summary>)) -> Self {
+        Entry {
+            file,
+            summary: result.as_ref().ok(),
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+        }
+    }
+}
+
+/// Table listing for every wallpaper of a batch either the description to
+/// use or why it failed.
+pub fn print_summary(results: &[(PathBuf, Result<Summary>)]) {
+    let width = results
+        .iter()
+        .map(|(file, _)| file.display().to_string().chars().count())
+        .max()
+        .unwrap_or(0);
+    println!("{}:", "Summary".bright_purple());
+    for (file, result) in results.iter() {
+        let file = format!("{:width$}", file.display().to_string(), width = width);
+        match result {
+            Ok(summary) => println!("  {}  {}  {}", "ok    ".green(), file, summary.picture()),
+            Err(e) => println!("  {}  {}  {:#}", "failed".red(), file, e),
+        }
+    }
+    let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+    println!(
+        "{} converted, {} failed",
+        results.len() - failed,
+        failed
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_of_wallpapers_sharing_a_file_name() {
+        let paths: Vec<PathBuf> = [
+            "a/Mojave.heic",
+            "b/Mojave.heic",
+            "c/Mojave-2.heic",
+            "Catalina.heic",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        assert_eq!(
+            unique_names(&paths),
+            vec![
+                Some("Mojave".to_string()),
+                Some("Mojave-2".to_string()),
+                Some("Mojave-2-2".to_string()),
+                Some("Catalina".to_string()),
+            ]
+        );
+    }
+}
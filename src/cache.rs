@@ -0,0 +1,329 @@
+// heic-to-dynamic-gnome-wallpaper
+// Copyright (C) 2022 Johannes Wünsche
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::collections::BTreeMap;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::schema::xml::File;
+use crate::util::encode::Encoding;
+use crate::util::resize::Resize;
+use crate::{DynamicWallpaper, Options};
+
+/// Name of the manifest kept next to the extracted images
+pub const MANIFEST: &str = ".cache.json";
+
+/// Remembers which images were extracted from which wallpaper with which
+/// settings, so that images which are still up to date are not extracted
+/// again. Only the images are cached, descriptions are always rewritten as
+/// they are cheap to create.
+pub struct Cache {
+    path: PathBuf,
+    source: Fingerprint,
+    settings: Settings,
+    /// Files of the previous run if it used the same wallpaper and settings
+    files: BTreeMap<String, Entry>,
+    /// Files of the previous run whatever its settings, removed when saving
+    /// unless they are written again
+    previous: Vec<String>,
+}
+
+/// Options which change the content of the extracted images
+#[derive(Serialize, Deserialize, PartialEq)]
+struct Settings {
+    encoding: Encoding,
+    resize: Option<Resize>,
+    sizes: Vec<Resize>,
+    convert_to_srgb: bool,
+}
+
+impl Settings {
+    fn new(options: &Options) -> Self {
+        Self {
+            encoding: options.encoding,
+            resize: options.resize,
+            sizes: options.sizes.clone(),
+            convert_to_srgb: options.convert_to_srgb,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    source: Fingerprint,
+    settings: Settings,
+    files: BTreeMap<String, Entry>,
+}
+
+/// Size and CRC32 checksum of a file. The checksum only detects changes to
+/// a file, it is no cryptographic hash.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy)]
+struct Fingerprint {
+    size: u64,
+    checksum: u32,
+}
+
+/// An extracted image file
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    image: usize,
+    #[serde(flatten)]
+    fingerprint: Fingerprint,
+}
+
+impl Cache {
+    /// Cache of the images extracted to `parent_directory`. A missing or
+    /// unreadable manifest is treated as empty.
+    pub fn open(
+        wallpaper: &DynamicWallpaper,
+        options: &Options,
+        parent_directory: &Path,
+    ) -> Result<Self> {
+        Ok(Self::load(
+            parent_directory,
+            fingerprint(&wallpaper.source),
+            Settings::new(options),
+        ))
+    }
+
+    fn load(parent_directory: &Path, source: Fingerprint, settings: Settings) -> Self {
+        let path = parent_directory.join(MANIFEST);
+        let manifest = std::fs::File::open(&path)
+            .ok()
+            .and_then(|file| serde_json::from_reader::<_, Manifest>(BufReader::new(file)).ok());
+        let previous = manifest
+            .iter()
+            .flat_map(|manifest| manifest.files.keys().cloned())
+            .collect();
+        let files = manifest
+            .filter(|manifest| manifest.source == source && manifest.settings == settings)
+            .map(|manifest| manifest.files)
+            .unwrap_or_default();
+        Self {
+            path,
+            source,
+            settings,
+            files,
+            previous,
+        }
+    }
+
+    /// Whether all variants of the file were extracted from the image before
+    /// and have not been changed since.
+    pub fn is_current(&self, image: usize, file: &File) -> bool {
+        file.paths().into_iter().all(|path| {
+            matches!(
+                (self.files.get(path), fingerprint_file(path)),
+                (Some(entry), Some(fingerprint))
+                    if entry.image == image && entry.fingerprint == fingerprint
+            )
+        })
+    }
+
+    /// Record the extracted files in the manifest and remove the files of
+    /// the previous run which are not part of this one.
+    pub fn save(self, files: &BTreeMap<usize, File>) -> Result<()> {
+        let mut entries = BTreeMap::new();
+        for (image, file) in files.iter() {
+            for path in file.paths() {
+                let fingerprint = fingerprint_file(path).ok_or_else(|| {
+                    anyhow::Error::msg(format!("Cannot read extracted image \"{}\"", path))
+                })?;
+                entries.insert(
+                    path.to_string(),
+                    Entry {
+                        image: *image,
+                        fingerprint,
+                    },
+                );
+            }
+        }
+        let directory = self.path.parent();
+        for stale in self
+            .previous
+            .iter()
+            .filter(|path| !entries.contains_key(*path))
+        {
+            // Only remove files next to the manifest, which are ours
+            if Path::new(stale).parent() == directory {
+                if let Err(e) = std::fs::remove_file(stale) {
+                    if e.kind() != std::io::ErrorKind::NotFound {
+                        return Err(Error::Io {
+                            path: stale.into(),
+                            source: e,
+                        }
+                        .into());
+                    }
+                }
+            }
+        }
+        let manifest = Manifest {
+            source: self.source,
+            settings: self.settings,
+            files: entries,
+        };
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .open(&self.path)?;
+        serde_json::to_writer_pretty(BufWriter::new(file), &manifest)?;
+        Ok(())
+    }
+}
+
+fn fingerprint(data: &[u8]) -> Fingerprint {
+    Fingerprint {
+        size: data.len() as u64,
+        checksum: crc32fast::hash(data),
+    }
+}
+
+fn fingerprint_file(path: &str) -> Option<Fingerprint> {
+    std::fs::read(path).ok().map(|data| fingerprint(&data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty directory for a single test
+    fn directory(test: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("heic-cache-{}-{}", std::process::id(), test));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn cache(directory: &Path, options: &Options) -> Cache {
+        Cache::load(directory, fingerprint(b"wallpaper"), Settings::new(options))
+    }
+
+    /// Write an extracted image and the file referring to it
+    fn image(directory: &Path, name: &str, content: &str) -> File {
+        let path = directory.join(name);
+        std::fs::write(&path, content).unwrap();
+        File::Single(path.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn current_file_kept() {
+        let directory = directory("current");
+        let options = Options::default();
+        let file = image(&directory, "0.png", "image");
+        cache(&directory, &options)
+            .save(&BTreeMap::from([(0, file.clone())]))
+            .unwrap();
+
+        let cache = cache(&directory, &options);
+        assert!(cache.is_current(0, &file));
+        assert!(!cache.is_current(1, &file));
+        image(&directory, "0.png", "changed");
+        assert!(!cache.is_current(0, &file));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn settings_change_invalidates() {
+        let directory = directory("settings");
+        let options = Options::default();
+        let file = image(&directory, "0.png", "image");
+        cache(&directory, &options)
+            .save(&BTreeMap::from([(0, file.clone())]))
+            .unwrap();
+
+        let changed = Options {
+            convert_to_srgb: !options.convert_to_srgb,
+            ..Options::default()
+        };
+        assert!(!cache(&directory, &changed).is_current(0, &file));
+        let other_source = Cache::load(&directory, fingerprint(b"other"), Settings::new(&options));
+        assert!(!other_source.is_current(0, &file));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn stale_files_removed() {
+        let directory = directory("stale");
+        let options = Options::default();
+        let kept = image(&directory, "0.png", "image");
+        let stale = image(&directory, "1.png", "image");
+        cache(&directory, &options)
+            .save(&BTreeMap::from([(0, kept.clone()), (1, stale.clone())]))
+            .unwrap();
+
+        // Also removed if the previous run used other settings
+        let changed = Options {
+            convert_to_srgb: !options.convert_to_srgb,
+            ..Options::default()
+        };
+        cache(&directory, &changed)
+            .save(&BTreeMap::from([(0, kept.clone())]))
+            .unwrap();
+        assert!(Path::new(kept.path()).exists());
+        assert!(!Path::new(stale.path()).exists());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn foreign_files_kept() {
+        let directory = directory("foreign");
+        let outside = directory.join("outside");
+        std::fs::create_dir_all(&outside).unwrap();
+        let options = Options::default();
+        let foreign = image(&outside, "0.png", "image");
+        let manifest = Manifest {
+            source: fingerprint(b"wallpaper"),
+            settings: Settings::new(&options),
+            files: BTreeMap::from([(
+                foreign.path().to_string(),
+                Entry {
+                    image: 0,
+                    fingerprint: fingerprint(b"image"),
+                },
+            )]),
+        };
+        std::fs::write(
+            directory.join(MANIFEST),
+            serde_json::to_string(&manifest).unwrap(),
+        )
+        .unwrap();
+
+        cache(&directory, &options).save(&BTreeMap::new()).unwrap();
+        assert!(Path::new(foreign.path()).exists());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn corrupt_manifest_is_empty() {
+        let directory = directory("corrupt");
+        let options = Options::default();
+        let file = image(&directory, "0.png", "image");
+        std::fs::write(directory.join(MANIFEST), "{\"source\": ").unwrap();
+
+        let corrupt = cache(&directory, &options);
+        assert!(corrupt.files.is_empty());
+        assert!(corrupt.previous.is_empty());
+        assert!(!corrupt.is_current(0, &file));
+        corrupt.save(&BTreeMap::from([(0, file.clone())])).unwrap();
+        assert!(cache(&directory, &options).is_current(0, &file));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,60 @@
+// heic-to-dynamic-gnome-wallpaper
+// Copyright (C) 2022 Johannes Wünsche
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use colored::*;
+use heic_to_dynamic_gnome_wallpaper::{Progress, Stage};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::sync::Mutex;
+
+/// Colored progress messages and a progress bar on the terminal.
+#[derive(Default)]
+pub struct Console {
+    bar: Mutex<Option<ProgressBar>>,
+}
+
+impl Progress for Console {
+    fn message(&self, stage: Stage, message: &str) {
+        match stage {
+            Stage::Preparation => println!("{}: {}", "Preparation".bright_blue(), message),
+            Stage::Conversion => println!("{}: {}", "Conversion".green(), message),
+        }
+    }
+
+    fn warning(&self, message: &str) {
+        println!("{}: {}", "Warning".yellow(), message);
+    }
+
+    fn start_images(&self, total: usize) {
+        println!("{}:", "Conversion".green());
+        let pb = ProgressBar::new(total as u64).with_style(
+            ProgressStyle::default_bar()
+                .template("[{wide_bar}] {pos}/{len} [ETA: {eta_precise}]").unwrap()
+                .progress_chars("## "),
+        );
+        *self.bar.lock().unwrap() = Some(pb);
+    }
+
+    fn image_done(&self) {
+        if let Some(pb) = self.bar.lock().unwrap().as_ref() {
+            pb.inc(1);
+        }
+    }
+
+    fn finish_images(&self) {
+        if let Some(pb) = self.bar.lock().unwrap().take() {
+            pb.finish();
+        }
+    }
+}
@@ -0,0 +1,176 @@
+// heic-to-dynamic-gnome-wallpaper
+// Copyright (C) 2022 Johannes Wünsche
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::image::Output;
+use anyhow::Result;
+use std::process::Command;
+
+const GNOME_BACKGROUND_SCHEMA: &str = "org.gnome.desktop.background";
+
+/// Settings of a desktop environment holding its background, as found in the
+/// `org.gnome.desktop.background` schema.
+pub trait DesktopBackend {
+    /// Whether the setting exists, older versions lack `picture-uri-dark`.
+    fn has_key(&self, key: &str) -> Result<bool>;
+    fn set(&self, key: &str, value: &str) -> Result<()>;
+}
+
+/// Set the converted wallpaper as background of the desktop. Without a dark
+/// variant the same wallpaper is used in both styles.
+pub fn set_background(backend: &dyn DesktopBackend, output: &Output) -> Result<()> {
+    let picture = file_uri(&output.picture);
+    backend.set("picture-uri", &picture)?;
+    if backend.has_key("picture-uri-dark")? {
+        let picture_dark = output
+            .picture_dark
+            .as_deref()
+            .map(file_uri)
+            .unwrap_or(picture);
+        backend.set("picture-uri-dark", &picture_dark)?;
+    }
+    Ok(())
+}
+
+/// GNOME, configured through `gsettings`.
+pub struct Gnome;
+
+impl Gnome {
+    fn gsettings(&self, args: &[&str]) -> Result<String> {
+        let result = Command::new("gsettings").args(args).output().map_err(|e| {
+            anyhow::Error::msg(format!("Cannot run gsettings: {}", e))
+        })?;
+        if !result.status.success() {
+            return Err(anyhow::Error::msg(format!(
+                "gsettings {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&result.stderr).trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&result.stdout).to_string())
+    }
+}
+
+impl DesktopBackend for Gnome {
+    fn has_key(&self, key: &str) -> Result<bool> {
+        let keys = self.gsettings(&["list-keys", GNOME_BACKGROUND_SCHEMA])?;
+        Ok(keys.lines().any(|line| line.trim() == key))
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.gsettings(&["set", GNOME_BACKGROUND_SCHEMA, key, value])?;
+        Ok(())
+    }
+}
+
+/// `file://` URI of an absolute path, reserved characters are percent-encoded.
+pub fn file_uri(path: &str) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    const PICTURE: &str = "/home/user/Wallpapers/Big Sur/Big Sur.xml";
+    const PICTURE_URI: &str = "file:///home/user/Wallpapers/Big%20Sur/Big%20Sur.xml";
+    const DARK: &str = "/home/user/Wallpapers/Big Sur/2.png";
+    const DARK_URI: &str = "file:///home/user/Wallpapers/Big%20Sur/2.png";
+
+    /// Records the settings instead of changing them.
+    struct Recorder {
+        keys: Vec<&'static str>,
+        settings: RefCell<Vec<(String, String)>>,
+    }
+
+    impl Recorder {
+        fn new(keys: Vec<&'static str>) -> Self {
+            Self {
+                keys,
+                settings: RefCell::new(Vec::new()),
+            }
+        }
+
+        fn apply(self, picture_dark: Option<&str>) -> Vec<(String, String)> {
+            let output = Output {
+                picture: PICTURE.to_string(),
+                picture_dark: picture_dark.map(str::to_string),
+                images: Vec::new(),
+                schedule: Vec::new(),
+            };
+            set_background(&self, &output).unwrap();
+            self.settings.into_inner()
+        }
+    }
+
+    impl DesktopBackend for Recorder {
+        fn has_key(&self, key: &str) -> Result<bool> {
+            Ok(self.keys.contains(&key))
+        }
+
+        fn set(&self, key: &str, value: &str) -> Result<()> {
+            self.settings
+                .borrow_mut()
+                .push((key.to_string(), value.to_string()));
+            Ok(())
+        }
+    }
+
+    fn owned(settings: &[(&str, &str)]) -> Vec<(String, String)> {
+        settings
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn dark_variant() {
+        let recorder = Recorder::new(vec!["picture-uri", "picture-uri-dark"]);
+        assert_eq!(
+            recorder.apply(Some(DARK)),
+            owned(&[("picture-uri", PICTURE_URI), ("picture-uri-dark", DARK_URI)])
+        );
+    }
+
+    #[test]
+    fn without_dark_variant() {
+        let recorder = Recorder::new(vec!["picture-uri", "picture-uri-dark"]);
+        assert_eq!(
+            recorder.apply(None),
+            owned(&[
+                ("picture-uri", PICTURE_URI),
+                ("picture-uri-dark", PICTURE_URI)
+            ])
+        );
+    }
+
+    #[test]
+    fn without_dark_style() {
+        let recorder = Recorder::new(vec!["picture-uri"]);
+        assert_eq!(
+            recorder.apply(Some(DARK)),
+            owned(&[("picture-uri", PICTURE_URI)])
+        );
+    }
+}
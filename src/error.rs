@@ -0,0 +1,90 @@
+// heic-to-dynamic-gnome-wallpaper
+// Copyright (C) 2022 Johannes Wünsche
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::path::PathBuf;
+
+/// Reasons a wallpaper cannot be converted. Functions of this crate return
+/// [`anyhow::Error`]s, these can be recovered from them with
+/// [`anyhow::Error::downcast_ref`] or more conveniently with [`exit_code`].
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Cannot access \"{path}\"")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("\"{path}\" is no valid heic image: {reason}")]
+    InvalidContainer { path: PathBuf, reason: String },
+    #[error("No valid metadata found describing wallpaper! Please check if the mime field is available and carries an apple_desktop:h24, apple_desktop:solar or apple_desktop:apr value")]
+    NoMetadata,
+    #[error("The wallpaper is of the unsupported kind \"apple_desktop:{0}\", only h24, solar and apr are supported")]
+    UnsupportedMode(String),
+    #[error("Appearance wallpapers only consist of a light and a dark image and have no schedule")]
+    NoSchedule,
+    #[error("The plist describing the wallpaper is corrupt: {0}")]
+    CorruptPlist(String),
+    #[error(
+        "Image {index} described in metadata does not exist, the container holds {count} images"
+    )]
+    ImageIndexOutOfRange { index: usize, count: usize },
+    #[error("Could not decode the image data: {0}")]
+    DecodeFailed(String),
+    #[error("Could not encode the image: {0}")]
+    EncodeFailed(String),
+    /// A GNOME slideshow, background properties or wallpaper manifest which
+    /// cannot be read
+    #[error("Invalid wallpaper description: {0}")]
+    InvalidDescription(String),
+    #[error("Invalid color profile: {0}")]
+    InvalidProfile(String),
+    /// A value given on the command line which cannot be used
+    #[error("{0}")]
+    InvalidArgument(String),
+}
+
+impl Error {
+    /// Exit code of the command line tool for this kind of error. 1 is used
+    /// for errors of other kinds, 2 for invalid arguments like for those
+    /// rejected by the argument parser.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io { .. } => 3,
+            Error::InvalidContainer { .. } => 4,
+            Error::NoMetadata => 5,
+            Error::UnsupportedMode(_) | Error::NoSchedule => 6,
+            Error::CorruptPlist(_) => 7,
+            Error::ImageIndexOutOfRange { .. } => 8,
+            Error::DecodeFailed(_) => 9,
+            Error::EncodeFailed(_) => 10,
+            Error::InvalidDescription(_) => 11,
+            Error::InvalidProfile(_) => 12,
+            Error::InvalidArgument(_) => 2,
+        }
+    }
+}
+
+/// Exit code for the first [`Error`] found in the chain of `error`. Plain
+/// I/O errors are treated like [`Error::Io`], everything else gets 1.
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    for cause in error.chain() {
+        if let Some(error) = cause.downcast_ref::<Error>() {
+            return error.exit_code();
+        }
+        if cause.is::<std::io::Error>() {
+            return 3;
+        }
+    }
+    1
+}
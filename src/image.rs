@@ -1,70 +1,359 @@
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
 use std::path::Path;
 
+use crate::cache::Cache;
+use crate::error::Error;
+use crate::progress::{Progress, Stage};
 use crate::schema::xml::{
     Background,
     Image::{Static, Transition},
+    File, Size, StartTime, Wallpapers,
 };
-use crate::serializer::GnomeXMLBackgroundSerializer;
-use crate::util::png;
-use crate::DAY_SECS;
+use crate::serializer::{GnomeXMLBackgroundSerializer, GnomeXMLPropertiesSerializer};
+use crate::util::resize::{Resize, ResizeMode};
+use crate::util::{color, decode, encode, path, resize, time};
+use crate::{DynamicWallpaper, Options, DAY_SECS};
 use anyhow::Result;
-use colored::*;
-use libheif_rs::HeifContext;
+use chrono::{Datelike, NaiveDate};
+use libheif_rs::ImageHandle;
+use rayon::prelude::*;
+use serde::Serialize;
 use std::io::BufWriter;
 
-pub struct ImagePoint<'a> {
-    pub image_ctx: &'a HeifContext<'a>,
-    pub img_id: u32,
-    pub index: usize,
-    pub background: &'a mut Background,
-    pub parent_directory: &'a Path,
-    pub start_time: f32,
+/// Files to use as `picture-uri` and `picture-uri-dark` for a converted
+/// wallpaper, either a slideshow description or a single image.
+#[derive(Debug, Serialize)]
+pub struct Output {
+    pub picture: String,
+    pub picture_dark: Option<String>,
+    /// All extracted images
+    pub images: Vec<String>,
+    /// Order and time of day the images are shown at, empty for appearance
+    /// wallpapers
+    pub schedule: Vec<ScheduleEntry>,
+}
+
+/// An image shown by a schedule.
+#[derive(Debug, Serialize)]
+pub struct ScheduleEntry {
+    /// Time of day formatted as HH:MM:SS
+    pub time: String,
+    pub image: usize,
+    pub dark: bool,
+}
+
+/// An image of the container planned to be shown at a time of day, given as
+/// fraction of the day.
+#[derive(Debug, Clone)]
+pub struct Slot {
     pub time: f32,
-    pub next_time: f32,
-}
-
-pub fn process_img(pt: ImagePoint) -> Result<()> {
-    let prim_image = pt.image_ctx.image_handle(pt.img_id).unwrap();
-    let number_of_images = pt.image_ctx.number_of_top_level_images();
-    png::write_png(
-        format!("{}/{}.png", pt.parent_directory.to_string_lossy(), pt.index).as_str(),
-        prim_image,
-    )?;
-
-    // Add to Background Structure
-    pt.background.images.push(Static {
-        duration: 1f32,
-        file: format!("{}/{}.png", pt.parent_directory.to_string_lossy(), pt.index),
-        idx: pt.index,
-    });
+    pub image: usize,
+    /// Whether the image belongs to the dark style
+    pub dark: bool,
+}
 
-    pt.background.images.push(Transition {
-        kind: "overlay".to_string(),
-        duration: {
-            if pt.index < number_of_images - 1 {
-                (pt.time - pt.next_time).abs() * DAY_SECS - 1.0
-            } else {
-                (((pt.time - 1.0).abs() + pt.start_time) * DAY_SECS - 1.0).ceil()
+/// A single extracted image and the time of day, as fraction of the day, at
+/// which it is shown.
+pub struct Frame {
+    pub time: f32,
+    pub file: File,
+}
+
+/// Decode the image, bring it to the requested resolution and write it in the
+/// requested format. Files with several sizes get one variant per size.
+fn convert(handle: &ImageHandle, file: &File, options: &Options) -> Result<()> {
+    let mut image = decode::decode(handle, options.encoding.depth)?;
+    let mut icc = color::icc_profile(handle)?;
+    if let (true, Some(profile)) = (options.convert_to_srgb, &icc) {
+        image = color::convert_to_srgb(image, profile)?;
+        icc = None;
+    }
+    match file {
+        File::Single(file) => {
+            let image = match &options.resize {
+                Some(target) => resize::resize(image, target),
+                None => image,
+            };
+            encode::write_image(file, &image, icc.as_deref(), &options.encoding)
+        }
+        File::Sizes(sizes) => {
+            for size in sizes.iter() {
+                let target = options
+                    .sizes
+                    .iter()
+                    .find(|target| (target.width, target.height) == (size.width, size.height))
+                    .copied()
+                    .unwrap_or(Resize {
+                        width: size.width,
+                        height: size.height,
+                        mode: ResizeMode::Fill,
+                    });
+                let variant = resize::resize(image.clone(), &target);
+                encode::write_image(&size.file, &variant, icc.as_deref(), &options.encoding)?;
             }
+            Ok(())
+        }
+    }
+}
+
+/// Extract the images to the files given by their image index. Images are
+/// decoded and encoded on the rayon thread pool, every worker reading the
+/// container with its own context. Files extracted to `parent_directory` by
+/// an earlier run with the same settings are kept if they are unchanged.
+pub fn process_images(
+    wallpaper: &DynamicWallpaper,
+    files: &BTreeMap<usize, File>,
+    options: &Options,
+    parent_directory: &Path,
+    progress: &dyn Progress,
+) -> Result<()> {
+    let source = wallpaper.source();
+    let cache = Cache::open(wallpaper, options, parent_directory)?;
+    let pending: BTreeMap<&usize, &File> = files
+        .iter()
+        .filter(|(image, file)| !cache.is_current(**image, file))
+        .collect();
+    if pending.len() < files.len() {
+        progress.message(
+            Stage::Conversion,
+            &format!(
+                "{} of {} images are up to date.",
+                files.len() - pending.len(),
+                files.len()
+            ),
+        );
+    }
+    if pending.is_empty() {
+        return cache.save(files);
+    }
+
+    progress.start_images(pending.len());
+    pending
+        .par_iter()
+        .map_init(
+            || source.open_context(),
+            |context, (image, file)| {
+                let context = context
+                    .as_ref()
+                    .map_err(|e| Error::DecodeFailed(e.to_string()))?;
+                convert(&source.image_handle(context, **image)?, file, options)?;
+                progress.image_done();
+                Ok(())
+            },
+        )
+        .collect::<Result<()>>()?;
+    progress.finish_images();
+    cache.save(files)
+}
+
+/// Paths the images used by the slots are written to. Each image is only
+/// written once and named after its index in the wallpaper, so that the
+/// names stay the same when the schedule changes.
+pub fn plan_files(
+    slots: &[Slot],
+    parent_directory: &Path,
+    options: &Options,
+) -> Result<BTreeMap<usize, File>> {
+    let mut files = BTreeMap::new();
+    for slot in slots {
+        if let Entry::Vacant(entry) = files.entry(slot.image) {
+            entry.insert(plan_file(parent_directory, slot.image, options)?);
+        }
+    }
+    Ok(files)
+}
+
+/// File for the image with the given `index`, offered in every requested size
+/// which are named like `0-1920x1080.png`.
+fn plan_file(parent_directory: &Path, index: usize, options: &Options) -> Result<File> {
+    let extension = options.encoding.format.extension();
+    if options.sizes.is_empty() {
+        let name = format!("{}.{}", index, extension);
+        return Ok(File::Single(path::file_in(parent_directory, &name)?));
+    }
+    options
+        .sizes
+        .iter()
+        .map(|size| {
+            let name = format!("{}-{}x{}.{}", index, size.width, size.height, extension);
+            Ok(Size {
+                width: size.width,
+                height: size.height,
+                file: path::file_in(parent_directory, &name)?,
+            })
+        })
+        .collect::<Result<_>>()
+        .map(File::Sizes)
+}
+
+pub fn schedule(slots: &[Slot]) -> Vec<ScheduleEntry> {
+    slots
+        .iter()
+        .map(|slot| ScheduleEntry {
+            time: time::format_time_of_day(slot.time),
+            image: slot.image,
+            dark: slot.dark,
+        })
+        .collect()
+}
+
+pub fn frames<'a, I: IntoIterator<Item = &'a Slot>>(
+    slots: I,
+    files: &BTreeMap<usize, File>,
+) -> Vec<Frame> {
+    slots
+        .into_iter()
+        .map(|slot| Frame {
+            time: slot.time,
+            file: files[&slot.image].clone(),
+        })
+        .collect()
+}
+
+/// Schedule showing the slots, which have to be sorted by their time of day.
+pub fn plan_background(
+    slots: &[Slot],
+    files: &BTreeMap<usize, File>,
+    date: NaiveDate,
+) -> Background {
+    let mut background = new_background(date, slots.first().map(|s| s.time).unwrap_or(0.0));
+    add_frames(&mut background, &frames(slots, files));
+    background
+}
+
+pub fn new_background(date: NaiveDate, start_time: f32) -> Background {
+    let start_seconds = (time::wrap_time_of_day(start_time) * DAY_SECS) as u32;
+    Background {
+        images: Vec::new(),
+        starttime: StartTime {
+            year: date.year(),
+            month: date.month(),
+            day: date.day(),
+            hour: time::to_rem_hours(start_seconds),
+            minute: time::to_rem_min(start_seconds),
+            second: time::to_rem_sec(start_seconds),
         },
-        from: format!("{}/{}.png", pt.parent_directory.to_string_lossy(), pt.index),
-        to: format!("{}/{}.png", pt.parent_directory.to_string_lossy(), {
-            if pt.index < number_of_images - 1 {
-                pt.index + 1
-            } else {
-                0
-            }
-        }),
-        idx: pt.index,
-    });
+    }
+}
+
+/// Add static images and transitions for frames sorted by their time of day.
+/// The last frame transitions back into the first one on the next day.
+pub fn add_frames(background: &mut Background, frames: &[Frame]) {
+    let start_time = match frames.first() {
+        Some(frame) => frame.time,
+        None => return,
+    };
+    for (index, frame) in frames.iter().enumerate() {
+        let next = frames.get(index + 1);
+        background.images.push(Static {
+            duration: 1f32,
+            file: frame.file.clone(),
+            idx: index,
+        });
+
+        background.images.push(Transition {
+            kind: "overlay".to_string(),
+            duration: match next {
+                Some(next) => (frame.time - next.time).abs() * DAY_SECS - 1.0,
+                None => (((frame.time - 1.0).abs() + start_time) * DAY_SECS - 1.0).ceil(),
+            },
+            from: frame.file.clone(),
+            to: next.unwrap_or(&frames[0]).file.clone(),
+            idx: index,
+        });
+    }
+
+    // Valify time range
+    let total_time = background
+        .images
+        .iter()
+        .fold(0f32, |acc, image| match image {
+            Static { duration, .. } => acc + duration,
+            Transition { duration, .. } => acc + duration,
+        });
+
+    if total_time < DAY_SECS {
+        if let Some(Static {
+            ref mut duration, ..
+        }
+        | Transition {
+            ref mut duration, ..
+        }) = background.images.last_mut()
+        {
+            *duration = (*duration + (DAY_SECS - total_time)).ceil();
+        }
+    }
+}
+
+/// Extract all images used by the slots and write the schedule describing
+/// them. Returns the written files by their image index, which may be reused
+/// for further schedules.
+pub fn export_schedule(
+    wallpaper: &DynamicWallpaper,
+    slots: &[Slot],
+    dark_image: Option<usize>,
+    options: &Options,
+    parent_directory: &Path,
+    image_name: &str,
+    progress: &dyn Progress,
+) -> Result<(Output, BTreeMap<usize, File>)> {
+    let date = options.date;
+    let format = options.encoding.format;
+    let mut files = plan_files(slots, parent_directory, options)?;
+    let dark_frames = frames(slots.iter().filter(|slot| slot.dark), &files);
+    // The dark style may use a single image which is not part of the schedule
+    let dark_image = match (dark_frames.is_empty(), dark_image) {
+        (true, Some(image)) if image < wallpaper.number_of_images() => {
+            let file = plan_file(parent_directory, image, options)?;
+            Some(files.entry(image).or_insert(file).path().to_string())
+        }
+        _ => None,
+    };
+
+    progress.message(
+        Stage::Preparation,
+        &format!("Found {} images", wallpaper.number_of_images()),
+    );
+    progress.message(
+        Stage::Conversion,
+        &format!(
+            "Converting embedded images to {} format...",
+            format.extension()
+        ),
+    );
+    process_images(wallpaper, &files, options, parent_directory, progress)?;
 
-    Ok(())
+    let mut background = plan_background(slots, &files, date);
+    let picture = save_xml(&mut background, parent_directory, image_name, progress)?;
+    let picture_dark = match dark_image {
+        Some(file) => Some(file),
+        None => save_dark_variant(&dark_frames, date, parent_directory, image_name, progress)?,
+    };
+    Ok((
+        Output {
+            picture,
+            picture_dark,
+            images: files
+                .values()
+                .flat_map(File::paths)
+                .map(str::to_string)
+                .collect(),
+            schedule: schedule(slots),
+        },
+        files,
+    ))
 }
 
-pub fn save_xml(xml: &mut Background, parent_directory: &Path, image_name: &str) -> Result<()> {
-    println!(
-        "{}: Creating xml description for new wallpaper...",
-        "Conversion".green(),
+pub fn save_xml(
+    xml: &mut Background,
+    parent_directory: &Path,
+    image_name: &str,
+    progress: &dyn Progress,
+) -> Result<String> {
+    progress.message(
+        Stage::Conversion,
+        "Creating xml description for new wallpaper...",
     );
     xml.images.sort_by(|a, b| match (a, b) {
         (
@@ -105,19 +394,62 @@ pub fn save_xml(xml: &mut Background, parent_directory: &Path, image_name: &str)
         ) => static_idx.cmp(transition_idx),
     });
 
-    println!("{}: Writing wallpaper description...", "Conversion".green(),);
+    progress.message(Stage::Conversion, "Writing wallpaper description...");
+    let path = path::file_in(parent_directory, &format!("{}.xml", image_name))?;
     let result_file = std::fs::OpenOptions::new()
         .write(true)
         .truncate(true)
         .create(true)
-        .open(format!(
-            "{}/{}.xml",
-            parent_directory.to_string_lossy(),
-            image_name,
-        ))?;
+        .open(&path)?;
     let mut result = BufWriter::new(result_file);
     let mut ser = GnomeXMLBackgroundSerializer::new(&mut result);
     ser.serialize(xml)?;
-    println!("{}: {}", "Conversion".green(), "Done!".green());
-    Ok(())
+    progress.message(Stage::Conversion, "Done!");
+    Ok(path)
+}
+
+pub fn save_properties(
+    xml: &Wallpapers,
+    parent_directory: &Path,
+    image_name: &str,
+    progress: &dyn Progress,
+) -> Result<String> {
+    progress.message(Stage::Conversion, "Writing wallpaper properties...");
+    let path = path::file_in(parent_directory, &format!("{}.xml", image_name))?;
+    let result_file = std::fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(&path)?;
+    let mut result = BufWriter::new(result_file);
+    let mut ser = GnomeXMLPropertiesSerializer::new(&mut result);
+    ser.serialize(xml)?;
+    progress.message(Stage::Conversion, "Done!");
+    Ok(path)
+}
+
+/// Dark variant made up of the frames designated for the dark style. A single
+/// frame is used as static image, multiple frames get their own schedule.
+pub fn save_dark_variant(
+    frames: &[Frame],
+    date: NaiveDate,
+    parent_directory: &Path,
+    image_name: &str,
+    progress: &dyn Progress,
+) -> Result<Option<String>> {
+    match frames {
+        [] => Ok(None),
+        [frame] => Ok(Some(frame.file.path().to_string())),
+        [first, ..] => {
+            let mut background = new_background(date, first.time);
+            add_frames(&mut background, frames);
+            save_xml(
+                &mut background,
+                parent_directory,
+                &format!("{}-dark", image_name),
+                progress,
+            )
+            .map(Some)
+        }
+    }
 }
@@ -0,0 +1,160 @@
+// heic-to-dynamic-gnome-wallpaper
+// Copyright (C) 2022 Johannes Wünsche
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::path::Path;
+
+use anyhow::Result;
+use libheif_rs::{HeifContext, ImageHandle};
+use serde::Serialize;
+
+use crate::error::Error;
+use crate::image::{self, ScheduleEntry};
+use crate::metadata::{self, WallpaperMeta, Xmp};
+use crate::{read_wallpaper, solar, timebased, Options, Silent};
+
+/// Everything known about a wallpaper, collected without converting it. Parts
+/// which cannot be read are left out, so that broken containers can be
+/// examined as far as possible.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    /// `h24`, `solar` or `apr` as found in the metadata
+    pub mode: Option<String>,
+    pub images: Vec<ImageInfo>,
+    /// Every XMP block of the images, those of the primary image first
+    pub xmp: Vec<Xmp>,
+    /// The plist embedded in the XMP with all of its fields
+    pub plist: Option<plist::Value>,
+    /// Order and time of day the images are shown at, empty for appearance
+    /// wallpapers
+    pub schedule: Vec<ScheduleEntry>,
+    /// Why the metadata or schedule could not be read
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImageInfo {
+    pub index: usize,
+    pub primary: bool,
+    pub width: u32,
+    pub height: u32,
+    pub bits_per_pixel: u8,
+    pub alpha: bool,
+    pub color_profile: Option<ColorProfile>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ColorProfile {
+    /// An embedded ICC profile of the given size in bytes
+    Icc { size: usize },
+    /// Color description by the coding-independent code points of ITU-T H.273
+    Nclx {
+        color_primaries: u8,
+        transfer_characteristics: u8,
+        matrix_coefficients: u8,
+        full_range: bool,
+    },
+}
+
+/// Examine the wallpaper at `path`. Solar schedules are computed with the
+/// location and date of the options.
+pub fn inspect<P: AsRef<Path>>(path: P, options: &Options) -> Result<Report> {
+    let path = path.as_ref();
+    let source = std::fs::read(path).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let context = HeifContext::read_from_bytes(&source).map_err(|e| Error::InvalidContainer {
+        path: path.to_path_buf(),
+        reason: e.to_string(),
+    })?;
+    let mut ids = vec![0; context.number_of_top_level_images()];
+    context.top_level_image_ids(&mut ids);
+    let images = ids
+        .iter()
+        .enumerate()
+        .map(|(index, id)| Ok(image_info(index, &context.image_handle(*id)?)))
+        .collect::<Result<Vec<_>>>()?;
+    let xmp = metadata::raw_xmp(&context);
+
+    let mut report = Report {
+        mode: None,
+        images,
+        xmp,
+        plist: None,
+        schedule: Vec::new(),
+        error: None,
+    };
+    if report.xmp.is_empty() {
+        report.error = Some("The image has no XMP metadata".to_string());
+        return Ok(report);
+    }
+    let mode = match metadata::get_wallpaper_metadata(&context) {
+        Ok(mode) => mode,
+        Err(e) => {
+            report.error = Some(e.to_string());
+            return Ok(report);
+        }
+    };
+    report.mode = Some(mode.name().to_string());
+    match mode.plist() {
+        Ok(plist) => report.plist = Some(plist),
+        Err(e) => {
+            report.error = Some(e.to_string());
+            return Ok(report);
+        }
+    }
+    match read_wallpaper(path) {
+        Ok(wallpaper) => match schedule(&wallpaper.meta, options) {
+            Ok(schedule) => report.schedule = schedule,
+            Err(e) => report.error = Some(e.to_string()),
+        },
+        Err(e) => report.error = Some(e.to_string()),
+    }
+    Ok(report)
+}
+
+fn image_info(index: usize, handle: &ImageHandle) -> ImageInfo {
+    let color_profile = match (handle.color_profile_raw(), handle.color_profile_nclx()) {
+        (Some(icc), _) => Some(ColorProfile::Icc {
+            size: icc.data.len(),
+        }),
+        (None, Some(nclx)) => Some(ColorProfile::Nclx {
+            color_primaries: nclx.color_primaries() as u8,
+            transfer_characteristics: nclx.transfer_characteristics() as u8,
+            matrix_coefficients: nclx.matrix_coefficients() as u8,
+            full_range: nclx.full_range_flag() != 0,
+        }),
+        (None, None) => None,
+    };
+    ImageInfo {
+        index,
+        primary: handle.is_primary(),
+        width: handle.width(),
+        height: handle.height(),
+        bits_per_pixel: handle.luma_bits_per_pixel(),
+        alpha: handle.has_alpha_channel(),
+        color_profile,
+    }
+}
+
+fn schedule(meta: &WallpaperMeta, options: &Options) -> Result<Vec<ScheduleEntry>> {
+    let slots = match meta {
+        WallpaperMeta::Time(plist) => timebased::plan(plist, options)?,
+        WallpaperMeta::Solar(plist) => solar::plan(plist, options, &Silent)?,
+        WallpaperMeta::Appearance(_) => Vec::new(),
+    };
+    Ok(image::schedule(&slots))
+}
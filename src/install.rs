@@ -0,0 +1,241 @@
+// heic-to-dynamic-gnome-wallpaper
+// Copyright (C) 2022 Johannes Wünsche
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::cache;
+use crate::error::Error;
+use crate::image::Output;
+use crate::parser::{GnomeXMLBackgroundParser, GnomeXMLPropertiesParser};
+use crate::progress::{Progress, Stage};
+use crate::schema::index::SeasonIndex;
+use crate::schema::xml::{File, Image, Wallpaper, Wallpapers};
+use crate::serializer::GnomeXMLPropertiesSerializer;
+use crate::util::path;
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// Directory GNOME Settings looks for wallpapers of the current user in,
+/// `$XDG_DATA_HOME/gnome-background-properties`.
+pub fn properties_directory() -> Result<PathBuf> {
+    let data_home = match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => std::env::var_os("HOME")
+            .map(|home| Path::new(&home).join(".local/share"))
+            .ok_or_else(|| anyhow::Error::msg("Cannot determine home directory, $HOME is not set"))?,
+    };
+    Ok(data_home.join("gnome-background-properties"))
+}
+
+/// Fail for names which cannot be used as file name of an entry, e.g. as
+/// they would refer to a file outside of its directory.
+pub fn check_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.contains("..") {
+        return Err(Error::InvalidArgument(format!(
+            "Invalid wallpaper name \"{}\", it must not be empty or contain /, \\ or ..",
+            name
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Register a converted wallpaper so that it is offered in GNOME Settings.
+/// Returns the path of the written entry.
+pub fn install(name: &str, output: &Output, progress: &dyn Progress) -> Result<PathBuf> {
+    check_name(name)?;
+    let directory = properties_directory()?;
+    std::fs::create_dir_all(&directory).map_err(|source| Error::Io {
+        path: directory.clone(),
+        source,
+    })?;
+    let path = directory.join(format!("{}.xml", name));
+    progress.message(
+        Stage::Conversion,
+        &format!("Registering wallpaper in {}...", directory.display()),
+    );
+
+    let properties = Wallpapers {
+        wallpapers: vec![Wallpaper {
+            name: name.to_string(),
+            filename: output.picture.clone(),
+            filename_dark: output.picture_dark.clone(),
+            options: "zoom".to_string(),
+            shade_type: "solid".to_string(),
+        }],
+    };
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(&path)
+        .map_err(|source| Error::Io {
+            path: path.clone(),
+            source,
+        })?;
+    let mut writer = BufWriter::new(file);
+    GnomeXMLPropertiesSerializer::new(&mut writer).serialize(&properties)?;
+    Ok(path)
+}
+
+/// Remove the entry of a wallpaper registered with [`install`] together with
+/// all images and descriptions it refers to. Only files in directories
+/// marked by the cache manifest of a conversion are removed, entries of
+/// other wallpapers leave their images untouched. Directories left empty are
+/// removed as well.
+pub fn uninstall(name: &str, progress: &dyn Progress) -> Result<()> {
+    check_name(name)?;
+    let entry = properties_directory()?.join(format!("{}.xml", name));
+    let file = std::fs::File::open(&entry)
+        .map_err(|source| Error::Io {
+            path: entry.clone(),
+            source,
+        })
+        .with_context(|| format!("No wallpaper named \"{}\" is installed", name))?;
+    let properties = GnomeXMLPropertiesParser::new(BufReader::new(file)).parse()?;
+
+    let mut files = BTreeSet::new();
+    for wallpaper in properties.wallpapers.iter() {
+        if !is_generated(&wallpaper.filename) {
+            progress.warning(&format!(
+                "\"{}\" has not been created by this tool, only its entry is removed.",
+                wallpaper.filename
+            ));
+            continue;
+        }
+        collect_files(&wallpaper.filename, &mut files)?;
+        if let Some(filename_dark) = &wallpaper.filename_dark {
+            collect_files(filename_dark, &mut files)?;
+        }
+        // Descriptions written next to the images which are not referenced
+        // by the entry itself
+        if let Some(directory) = Path::new(&wallpaper.filename).parent() {
+            for generated in [
+                format!("{}.xml", wallpaper.name),
+                format!("{}-seasons.json", wallpaper.name),
+            ] {
+                let generated = directory.join(generated);
+                if generated.exists() {
+                    collect_files(path::to_str(&generated)?, &mut files)?;
+                }
+            }
+            let cache = directory.join(cache::MANIFEST);
+            if cache.exists() {
+                files.insert(path::to_str(&cache)?.to_string());
+            }
+        }
+    }
+
+    // Descriptions may refer to images of other wallpapers
+    files.retain(|file| is_generated(file));
+    progress.message(
+        Stage::Conversion,
+        &format!("Removing {} generated files...", files.len()),
+    );
+    let mut directories = BTreeSet::new();
+    for file in files.iter() {
+        let path = Path::new(file);
+        match std::fs::remove_file(path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                progress.warning(&format!("\"{}\" does not exist anymore.", file))
+            }
+            Err(source) => {
+                return Err(Error::Io {
+                    path: path.to_path_buf(),
+                    source,
+                }
+                .into())
+            }
+        }
+        if let Some(directory) = path.parent() {
+            directories.insert(directory.to_path_buf());
+        }
+    }
+    for directory in directories.iter() {
+        // Fails for directories still containing other files, which are kept
+        let _ = std::fs::remove_dir(directory);
+    }
+
+    std::fs::remove_file(&entry).map_err(|source| Error::Io {
+        path: entry.clone(),
+        source,
+    })?;
+    progress.message(Stage::Conversion, "Done!");
+    Ok(())
+}
+
+/// Whether the file lies in a directory written by a conversion.
+fn is_generated(file: &str) -> bool {
+    Path::new(file)
+        .parent()
+        .is_some_and(|directory| directory.join(cache::MANIFEST).is_file())
+}
+
+/// Add the file and, for slideshows and schedule indices, every file they
+/// refer to.
+fn collect_files(file: &str, files: &mut BTreeSet<String>) -> Result<()> {
+    if !files.insert(file.to_string()) {
+        return Ok(());
+    }
+    let path = Path::new(file);
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("xml") if path.exists() => {
+            let reader = std::fs::File::open(path).map_err(|source| Error::Io {
+                path: path.to_path_buf(),
+                source,
+            })?;
+            let reader = BufReader::new(reader);
+            // Properties files of appearance wallpapers are no slideshows and
+            // do not refer to anything beyond the images already collected
+            if let Ok(background) = GnomeXMLBackgroundParser::new(reader).parse() {
+                for image in background.images.iter() {
+                    match image {
+                        Image::Static { file, .. } => insert_file(file, files),
+                        Image::Transition { from, to, .. } => {
+                            insert_file(from, files);
+                            insert_file(to, files);
+                        }
+                    }
+                }
+            }
+        }
+        Some("json") if path.exists() => {
+            let reader = std::fs::File::open(path).map_err(|source| Error::Io {
+                path: path.to_path_buf(),
+                source,
+            })?;
+            let index: SeasonIndex = serde_json::from_reader(BufReader::new(reader))
+                .map_err(|e| Error::InvalidDescription(format!("\"{}\": {}", file, e)))?;
+            for season in index.seasons.iter() {
+                collect_files(&season.file, files)?;
+                if let Some(file_dark) = &season.file_dark {
+                    collect_files(file_dark, files)?;
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn insert_file(file: &File, files: &mut BTreeSet<String>) {
+    match file {
+        File::Single(file) => {
+            files.insert(file.clone());
+        }
+        File::Sizes(sizes) => files.extend(sizes.iter().map(|size| size.file.clone())),
+    }
+}
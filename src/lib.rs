@@ -0,0 +1,246 @@
+// heic-to-dynamic-gnome-wallpaper
+// Copyright (C) 2022 Johannes Wünsche
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Conversion of MacOS's dynamic wallpapers stored in `.heic` image containers
+//! to dynamic wallpaper definitions usable in GNOME.
+//!
+//! A conversion reads the wallpaper with [`read_wallpaper`], optionally
+//! inspects the resulting schedule with [`plan_schedule`] and writes images
+//! and descriptions with [`export`].
+use anyhow::Result;
+use chrono::NaiveDate;
+use libheif_rs::{HeifContext, ImageHandle, ItemId};
+use std::path::Path;
+
+pub mod adjust;
+pub mod appearance;
+pub mod cache;
+pub mod desktop;
+pub mod error;
+pub mod image;
+pub mod inspect;
+pub mod install;
+pub mod metadata;
+pub mod pack;
+pub mod parser;
+pub mod progress;
+pub mod schema;
+pub mod serializer;
+pub mod solar;
+pub mod timebased;
+pub mod util;
+
+pub use adjust::{Adjustment, Anchor};
+pub use error::Error;
+pub use image::Output;
+pub use metadata::WallpaperMeta;
+pub use progress::{Progress, Silent, Stage};
+pub use schema::xml::Background;
+pub use solar::Period;
+pub use util::encode::{Depth, Encoding, Format};
+pub use util::resize::{Resize, ResizeMode};
+pub use util::sun::Location;
+
+pub const DAY_SECS: f32 = 86400.0;
+
+/// A dynamic wallpaper read from a `.heic` container together with the
+/// description of how its images are to be shown.
+pub struct DynamicWallpaper {
+    pub meta: WallpaperMeta,
+    image_ids: Vec<ItemId>,
+    /// Content of the container, from which contexts are created when images
+    /// are decoded, one for each thread.
+    source: Vec<u8>,
+}
+
+impl DynamicWallpaper {
+    pub fn number_of_images(&self) -> usize {
+        self.image_ids.len()
+    }
+
+    /// Handle of the image at position `idx` as referred to by the metadata.
+    pub fn image_handle(&self, idx: usize) -> Result<ImageHandle> {
+        let source = self.source();
+        source.image_handle(&source.open_context()?, idx)
+    }
+
+    pub fn source(&self) -> Source<'_> {
+        Source {
+            data: &self.source,
+            image_ids: &self.image_ids,
+        }
+    }
+}
+
+/// Content of a wallpaper's container which, unlike its context, can be
+/// shared between threads. Every thread decodes images with its own context.
+#[derive(Clone, Copy)]
+pub struct Source<'a> {
+    data: &'a [u8],
+    image_ids: &'a [ItemId],
+}
+
+impl<'a> Source<'a> {
+    pub fn open_context(&self) -> Result<HeifContext<'a>> {
+        Ok(HeifContext::read_from_bytes(self.data)?)
+    }
+
+    /// Handle of the image at position `idx` in a context of this container.
+    pub fn image_handle(&self, context: &HeifContext, idx: usize) -> Result<ImageHandle> {
+        let img_id = self
+            .image_ids
+            .get(idx)
+            .ok_or(Error::ImageIndexOutOfRange {
+                index: idx,
+                count: self.image_ids.len(),
+            })?;
+        Ok(context.image_handle(*img_id)?)
+    }
+}
+
+/// Settings influencing the schedule and images of a converted wallpaper.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Place the wallpaper is used at, solar wallpapers are approximated from
+    /// the azimuth of each image if unknown.
+    pub location: Option<Location>,
+    /// Day the sun positions of solar wallpapers are computed for.
+    pub date: NaiveDate,
+    /// Additionally create schedules for every period of the year.
+    pub period: Option<Period>,
+    /// Format the images are extracted in.
+    pub encoding: Encoding,
+    /// Resolution the images are brought to, they keep their size if unset.
+    pub resize: Option<Resize>,
+    /// Resolutions each image is offered in, GNOME picks the one best
+    /// matching the monitor. Takes precedence over `resize`.
+    pub sizes: Vec<Resize>,
+    /// Convert the colors of the images to sRGB instead of embedding their
+    /// color profile.
+    pub convert_to_srgb: bool,
+    /// Changes to the times at which the images are shown.
+    pub adjustment: Adjustment,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            location: None,
+            date: chrono::Local::now().date_naive(),
+            period: None,
+            encoding: Encoding::default(),
+            resize: None,
+            sizes: Vec::new(),
+            convert_to_srgb: false,
+            adjustment: Adjustment::default(),
+        }
+    }
+}
+
+pub fn read_wallpaper<P: AsRef<Path>>(path: P) -> Result<DynamicWallpaper> {
+    let path = path.as_ref();
+    let source = std::fs::read(path).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let context = HeifContext::read_from_bytes(&source).map_err(|e| Error::InvalidContainer {
+        path: path.to_path_buf(),
+        reason: e.message,
+    })?;
+    let meta = metadata::get_wallpaper_metadata(&context)?.decode()?;
+    let mut image_ids = vec![0; context.number_of_top_level_images()];
+    context.top_level_image_ids(&mut image_ids);
+    // The context borrows the source, which is kept by the wallpaper
+    drop(context);
+    Ok(DynamicWallpaper {
+        meta,
+        image_ids,
+        source,
+    })
+}
+
+/// Compute the GNOME schedule of a wallpaper without writing any files. Image
+/// paths are those [`export`] would create in `parent_directory`.
+pub fn plan_schedule(
+    wallpaper: &DynamicWallpaper,
+    options: &Options,
+    parent_directory: &Path,
+) -> Result<Background> {
+    let slots = match &wallpaper.meta {
+        WallpaperMeta::Time(plist) => timebased::plan(plist, options)?,
+        WallpaperMeta::Solar(plist) => solar::plan(plist, options, &Silent)?,
+        WallpaperMeta::Appearance(_) => return Err(Error::NoSchedule.into()),
+    };
+    let files = image::plan_files(&slots, parent_directory, options)?;
+    Ok(image::plan_background(&slots, &files, options.date))
+}
+
+/// Write the images of a wallpaper and the descriptions referring to them
+/// into `parent_directory`.
+pub fn export(
+    wallpaper: &DynamicWallpaper,
+    options: &Options,
+    parent_directory: &Path,
+    name: &str,
+    progress: &dyn Progress,
+) -> Result<Output> {
+    options.encoding.check()?;
+    if !options.convert_to_srgb
+        && !options.encoding.format.embeds_icc()
+        && util::color::icc_profile(&wallpaper.image_handle(0)?)?.is_some()
+    {
+        progress.warning(
+            "The color profile of the wallpaper cannot be embedded into the chosen format, use --convert-to-srgb to keep the colors.",
+        );
+    }
+    match &wallpaper.meta {
+        WallpaperMeta::Time(plist) => {
+            progress.message(Stage::Preparation, "Detected time-based wallpaper.");
+            let slots = timebased::plan(plist, options)?;
+            let dark_image = plist.appearance.as_ref().map(|ap| ap.dark as usize);
+            let (output, _) = image::export_schedule(
+                wallpaper,
+                &slots,
+                dark_image,
+                options,
+                parent_directory,
+                name,
+                progress,
+            )?;
+            Ok(output)
+        }
+        WallpaperMeta::Solar(plist) => {
+            progress.message(Stage::Preparation, "Detected solar-based wallpaper.");
+            let slots = solar::plan(plist, options, progress)?;
+            let dark_image = plist.appearance.as_ref().map(|ap| ap.dark as usize);
+            let (output, files) = image::export_schedule(
+                wallpaper,
+                &slots,
+                dark_image,
+                options,
+                parent_directory,
+                name,
+                progress,
+            )?;
+            solar::export_seasons(plist, &files, parent_directory, name, options, progress)?;
+            Ok(output)
+        }
+        WallpaperMeta::Appearance(plist) => {
+            progress.message(Stage::Preparation, "Detected appearance-based wallpaper.");
+            appearance::export(wallpaper, plist, options, parent_directory, name, progress)
+        }
+    }
+}
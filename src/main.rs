@@ -1,34 +1,502 @@
 use anyhow::Result;
+use chrono::NaiveDate;
 use colored::*;
-use libheif_rs::HeifContext;
-use std::path::Path;
+use heic_to_dynamic_gnome_wallpaper::{
+    desktop::{self, DesktopBackend, Gnome},
+    error, export, Error,
+    inspect::{self, ColorProfile},
+    install, pack, read_wallpaper, Adjustment, Anchor, Depth, Encoding, Format, Location, Options, Output, Period,
+    Progress, Resize, ResizeMode, Silent, Stage,
+};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
 
-use clap::{Arg, Command};
+use clap::{Arg, ArgAction, ArgMatches, Command};
 
-mod image;
-mod metadata;
-mod schema;
-mod serializer;
-mod solar;
-mod timebased;
-mod util;
+mod batch;
+mod console;
 
 const INPUT: &str = "IMAGE";
 const DIR: &str = "DIR";
 const NAME: &str = "NAME";
 const VERS: &str = "VERS";
+const LAT: &str = "LAT";
+const LON: &str = "LON";
+const DATE: &str = "DATE";
+const SEASONAL: &str = "SEASONAL";
+const XML: &str = "XML";
+const OUTPUT: &str = "OUTPUT";
+const SOURCE: &str = "SOURCE";
+const INSTALL: &str = "INSTALL";
+const UNINSTALL: &str = "UNINSTALL";
+const APPLY: &str = "APPLY";
+const JOBS: &str = "JOBS";
+const FORMAT: &str = "FORMAT";
+const QUALITY: &str = "QUALITY";
+const RESIZE: &str = "RESIZE";
+const RESIZE_MODE: &str = "RESIZE_MODE";
+const SIZES: &str = "SIZES";
+const DEPTH: &str = "DEPTH";
+const CONVERT_TO_SRGB: &str = "CONVERT_TO_SRGB";
+const MANIFEST: &str = "MANIFEST";
+const JSON: &str = "JSON";
+const QUIET: &str = "QUIET";
+const OUTPUT_FORMAT: &str = "OUTPUT_FORMAT";
+const RECURSIVE: &str = "RECURSIVE";
+const OFFSET: &str = "OFFSET";
+const ANCHOR: &str = "ANCHOR";
+const STRETCH_DAY: &str = "STRETCH_DAY";
 
-const DAY_SECS: f32 = 86400.0;
 const VERSION: Option<&str> = option_env!("CARGO_PKG_VERSION");
 
-fn main() -> Result<()> {
-    let matches = Command::new("heic-to-dynamic-gnome-wallpaper")
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {:?}", e);
+        std::process::exit(error::exit_code(&e));
+    }
+}
+
+fn run() -> Result<()> {
+    let matches = convert_args(Command::new("heic-to-dynamic-gnome-wallpaper"))
+        .arg(Arg::new(VERS)
+            .help("Print version")
+            .short('v')
+            .long("version")
+            .action(ArgAction::SetTrue)
+        )
+        .subcommand(convert_args(Command::new("convert")
+            .about("Convert dynamic wallpapers, the same as giving the images without subcommand")
+        ))
+        .subcommand(Command::new("pack")
+            .about("Create a time-based dynamic wallpaper from a GNOME xml wallpaper")
+            .arg(Arg::new(XML)
+                .help("GNOME xml wallpaper which should be transformed")
+                .num_args(1)
+                .value_name(XML)
+                .required(true)
+            )
+            .arg(Arg::new(OUTPUT)
+                .help("Path of the created heic image")
+                .long_help("Path of the created heic image. Default is the path of the given xml file with the extension replaced by .heic")
+                .short('o')
+                .long("output")
+                .num_args(1)
+                .value_name(OUTPUT)
+            )
+        )
+        .subcommand(Command::new("create")
+            .about("Create a time-based or solar dynamic wallpaper from a directory of images")
+            .arg(Arg::new(SOURCE)
+                .help("Directory containing the images and the manifest describing them")
+                .num_args(1)
+                .value_name("DIR")
+                .required(true)
+            )
+            .arg(Arg::new(MANIFEST)
+                .help("TOML or JSON file listing the images")
+                .long_help("TOML or JSON file listing the images with the time of day or the sun position they show. Default is manifest.toml or manifest.json in the given directory")
+                .short('m')
+                .long("manifest")
+                .num_args(1)
+                .value_name(MANIFEST)
+            )
+            .arg(Arg::new(OUTPUT)
+                .help("Path of the created heic image")
+                .long_help("Path of the created heic image. Default is the path of the given directory with the extension .heic")
+                .short('o')
+                .long("output")
+                .num_args(1)
+                .value_name(OUTPUT)
+            )
+        )
+        .subcommand(Command::new("inspect")
+            .about("Print the images, metadata and schedule of a dynamic wallpaper")
+            .arg(Arg::new(INPUT)
+                .help("Image which should be inspected")
+                .num_args(1)
+                .value_name(INPUT)
+                .required(true)
+            )
+            .args(sun_args())
+            .args(schedule_args())
+            .arg(Arg::new(JSON)
+                .help("Print the information as JSON")
+                .long("json")
+                .action(ArgAction::SetTrue)
+            )
+        )
+        .args_conflicts_with_subcommands(true)
+        .get_matches();
+
+    if matches.get_flag(VERS) {
+        println!("Version: {}", VERSION.unwrap_or("unknown"));
+        std::process::exit(0);
+    }
+    let matches = match matches.subcommand() {
+        Some(("convert", matches)) => matches.clone(),
+        _ => matches,
+    };
+
+    let json = matches.get_one::<String>(OUTPUT_FORMAT).map(String::as_str) == Some("json");
+    let quiet = json || matches.get_flag(QUIET);
+    let progress: Box<dyn Progress> = if quiet {
+        Box::new(Silent)
+    } else {
+        Box::new(console::Console::default())
+    };
+    let progress = progress.as_ref();
+
+    if let Some(name) = matches.get_one::<String>(UNINSTALL) {
+        install::uninstall(name.trim(), progress)?;
+        if json {
+            println!("{}", serde_json::json!({ "removed": name.trim() }));
+        } else if !quiet {
+            println!("{}: Removed {}", "Result".bright_purple(), name.trim());
+        }
+        return Ok(());
+    }
+
+    if let Some(("pack", matches)) = matches.subcommand() {
+        return run_pack(matches);
+    }
+    if let Some(("create", matches)) = matches.subcommand() {
+        return run_create(matches);
+    }
+    if let Some(("inspect", matches)) = matches.subcommand() {
+        return run_inspect(matches);
+    }
+
+    if let Some(&jobs) = matches.get_one::<u16>(JOBS) {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs as usize)
+            .build_global()?;
+    }
+
+    let period = matches
+        .get_one::<String>(SEASONAL)
+        .map(|period| match period.as_str() {
+            "week" => Period::Week,
+            _ => Period::Month,
+        });
+
+    let encoding = Encoding {
+        format: match matches.get_one::<String>(FORMAT) {
+            Some(format) => format.parse()?,
+            None => Format::Png,
+        },
+        depth: match matches.get_one::<String>(DEPTH).map(String::as_str) {
+            Some("16") => Depth::Sixteen,
+            _ => Depth::Eight,
+        },
+        quality: matches.get_one::<u8>(QUALITY).copied(),
+    };
+
+    let resize_mode = match matches.get_one::<String>(RESIZE_MODE) {
+        Some(mode) => mode.parse()?,
+        None => ResizeMode::Fill,
+    };
+    let resize = matches
+        .get_one::<String>(RESIZE)
+        .map(|resolution| Resize::parse(resolution, resize_mode))
+        .transpose()?;
+    let sizes = match matches.get_one::<String>(SIZES) {
+        Some(sizes) => sizes
+            .split(',')
+            .map(|resolution| Resize::parse(resolution, resize_mode))
+            .collect::<Result<Vec<_>>>()?,
+        None => Vec::new(),
+    };
+
+    let options = Options {
+        location: read_location(&matches)?,
+        date: read_date(&matches)?,
+        period,
+        encoding,
+        resize,
+        sizes,
+        convert_to_srgb: matches.get_flag(CONVERT_TO_SRGB),
+        adjustment: read_adjustment(&matches)?,
+    };
+
+    let mut inputs: Vec<PathBuf> = matches
+        .get_many::<String>(INPUT)
+        .map(|paths| paths.map(PathBuf::from).collect())
+        .unwrap_or_default();
+    let recursive = matches.get_one::<String>(RECURSIVE);
+    if let Some(directory) = recursive {
+        inputs.extend(batch::find_wallpapers(Path::new(directory))?);
+    }
+
+    if inputs.len() == 1 && recursive.is_none() {
+        let summary = convert(&inputs[0], None, false, &matches, &options, &Gnome, progress)?;
+        if json {
+            println!("{}", serde_json::to_string_pretty(&summary)?);
+        } else if !quiet {
+            print_result(&summary, matches.get_flag(APPLY));
+        }
+        return Ok(());
+    }
+    if inputs.is_empty() {
+        return Err(Error::InvalidArgument("No image given to convert".to_string()).into());
+    }
+    if matches.contains_id(NAME) || matches.get_flag(APPLY) {
+        return Err(Error::InvalidArgument(
+            "--name and --apply can only be used when converting a single image".to_string(),
+        )
+        .into());
+    }
+
+    let names = batch::unique_names(&inputs);
+    let results: Vec<(PathBuf, Result<Summary>)> = inputs
+        .into_iter()
+        .zip(names)
+        .map(|(path, name)| {
+            progress.message(
+                Stage::Preparation,
+                &format!("Converting {}...", path.display()),
+            );
+            let result = convert(
+                &path,
+                name.as_deref(),
+                true,
+                &matches,
+                &options,
+                &Gnome,
+                progress,
+            );
+            // Errors are printed even with --quiet, on stderr to keep the
+            // results apart
+            if let Err(e) = &result {
+                eprintln!("{}: {:#}", "Error".red(), e);
+            }
+            (path, result)
+        })
+        .collect();
+    if json {
+        let entries: Vec<batch::Entry> = results.iter().map(batch::Entry::from).collect();
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else if !quiet {
+        batch::print_summary(&results);
+    }
+    let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+    if failed > 0 {
+        return Err(anyhow::Error::msg(format!(
+            "{} of {} wallpapers could not be converted",
+            failed,
+            results.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Convert a single wallpaper. In a batch every wallpaper is written to its
+/// own subdirectory, also of the directory given with --dir. `name` takes
+/// precedence over the name derived from the file.
+fn convert(
+    path: &Path,
+    name: Option<&str>,
+    batch: bool,
+    matches: &ArgMatches,
+    options: &Options,
+    backend: &dyn DesktopBackend,
+    progress: &dyn Progress,
+) -> Result<Summary> {
+    let name = match name.or(matches.get_one::<String>(NAME).map(String::as_str)) {
+        Some(name) => name.trim().to_string(),
+        None => path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| {
+                Error::InvalidArgument(format!(
+                    "Cannot derive a name from \"{}\", use --name",
+                    path.display()
+                ))
+            })?
+            .to_string(),
+    };
+    install::check_name(&name)?;
+
+    progress.message(Stage::Preparation, "Fetch metadata from image...");
+    let wallpaper = read_wallpaper(path)?;
+
+    let parent_directory = match matches.get_one::<String>(DIR) {
+        Some(directory) => {
+            let mut directory = Path::new(directory.trim()).to_path_buf();
+            if batch {
+                directory.push(&name);
+            }
+            directory
+        }
+        None => {
+            let mut directory = path
+                .canonicalize()
+                .map_err(|source| Error::Io {
+                    path: path.to_path_buf(),
+                    source,
+                })?
+                .ancestors()
+                .nth(1)
+                .ok_or_else(|| {
+                    Error::InvalidArgument(format!(
+                        "Cannot get parent of given image path: \"{}\"",
+                        path.display()
+                    ))
+                })?
+                .to_path_buf();
+            directory.push(&name);
+            directory
+        }
+    };
+    if !parent_directory.exists() {
+        std::fs::create_dir_all(&parent_directory)?
+    }
+    let parent_directory = parent_directory.canonicalize()?;
+
+    let output = export(&wallpaper, options, &parent_directory, &name, progress)?;
+
+    let registered = if matches.get_flag(INSTALL) {
+        Some(install::install(&name, &output, progress)?)
+    } else {
+        None
+    };
+    if matches.get_flag(APPLY) {
+        progress.message(Stage::Conversion, "Setting wallpaper as background...");
+        desktop::set_background(backend, &output)?;
+    }
+    Ok(Summary {
+        name,
+        mode: wallpaper.meta.name(),
+        output,
+        registered,
+    })
+}
+
+fn print_result(summary: &Summary, applied: bool) {
+    let output = &summary.output;
+    println!("{}: Use {} as picture-uri", "Result".bright_purple(), output.picture);
+    if let Some(picture_dark) = &output.picture_dark {
+        println!(
+            "{}: Use {} as picture-uri-dark",
+            "Result".bright_purple(),
+            picture_dark
+        );
+    }
+    if let Some(entry) = &summary.registered {
+        println!(
+            "{}: Registered wallpaper in {}",
+            "Result".bright_purple(),
+            entry.display()
+        );
+    }
+    if applied {
+        println!("{}: Set {} as background", "Result".bright_purple(), summary.name);
+    }
+}
+
+/// Result of a conversion as printed with `--output json`
+#[derive(Serialize)]
+struct Summary {
+    name: String,
+    mode: &'static str,
+    #[serde(flatten)]
+    output: Output,
+    /// Entry created in gnome-background-properties with --install
+    registered: Option<PathBuf>,
+}
+
+impl Summary {
+    fn picture(&self) -> &str {
+        &self.output.picture
+    }
+}
+
+fn run_pack(matches: &ArgMatches) -> Result<()> {
+    let xml = Path::new(
+        matches
+            .get_one::<String>(XML)
+            .ok_or_else(|| anyhow::Error::msg("Could not read XML"))?,
+    );
+    let output = match matches.get_one::<String>(OUTPUT) {
+        Some(output) => Path::new(output.trim()).to_path_buf(),
+        None => xml.with_extension("heic"),
+    };
+    let base_directory = xml
+        .parent()
+        .ok_or_else(|| {
+            Error::InvalidArgument(format!(
+                "Cannot get parent of given xml path: \"{}\"",
+                xml.display()
+            ))
+        })?
+        .to_path_buf();
+
+    let progress = console::Console::default();
+    progress.message(Stage::Preparation, "Reading wallpaper description...");
+    let background = pack::read_background(xml)?;
+    pack::pack(&background, &base_directory, &output, &progress)?;
+    println!(
+        "{}: Created {}",
+        "Result".bright_purple(),
+        output.display()
+    );
+    Ok(())
+}
+
+fn run_create(matches: &ArgMatches) -> Result<()> {
+    let source = Path::new(
+        matches
+            .get_one::<String>(SOURCE)
+            .ok_or_else(|| anyhow::Error::msg("Could not read DIR"))?,
+    );
+    let manifest = match matches.get_one::<String>(MANIFEST) {
+        Some(manifest) => Path::new(manifest.trim()).to_path_buf(),
+        None => ["manifest.toml", "manifest.json"]
+            .iter()
+            .map(|file| source.join(file))
+            .find(|path| path.exists())
+            .ok_or_else(|| {
+                Error::InvalidArgument(format!(
+                    "No manifest.toml or manifest.json found in \"{}\"",
+                    source.display()
+                ))
+            })?,
+    };
+    let output = match matches.get_one::<String>(OUTPUT) {
+        Some(output) => Path::new(output.trim()).to_path_buf(),
+        None => source.with_extension("heic"),
+    };
+
+    let progress = console::Console::default();
+    progress.message(Stage::Preparation, "Reading manifest...");
+    let manifest = pack::read_manifest(&manifest)?;
+    pack::create(&manifest, source, &output, &progress)?;
+    println!(
+        "{}: Created {}",
+        "Result".bright_purple(),
+        output.display()
+    );
+    Ok(())
+}
+
+/// Arguments of the conversion, given either without subcommand or to
+/// `convert`.
+fn convert_args(command: Command) -> Command {
+    command
         .arg(Arg::new(INPUT)
-             .help("Image which should be transformed")
-             .num_args(1)
+             .help("Images which should be transformed")
+             .long_help("Images which should be transformed. When several images are given, each is converted into its own subdirectory and the conversion continues past failures.")
+             .num_args(1..)
              .value_name(INPUT)
             //  .required(true)
         )
+        .arg(Arg::new(RECURSIVE)
+            .help("Convert all heic images found in a directory and its subdirectories")
+            .long_help("Convert all .heic and .heif images found in the given directory and its subdirectories, each into its own subdirectory. The conversion continues past failures and a summary is printed at the end.")
+            .short('r')
+            .long("recursive")
+            .num_args(1)
+            .value_name("DIR")
+        )
         .arg(Arg::new(NAME)
             .help("Wallpaper name")
             .long_help("Wallpaper name. If not specified, the file name is used by default")
@@ -45,106 +513,298 @@ fn main() -> Result<()> {
              .num_args(1)
              .value_name(DIR)
             )
-        .arg(Arg::new(VERS)
-            .help("Print version")
-            .short('v')
-            .long("version")
-            .value_name("VERSION")
-            .num_args(0)
+        .args(sun_args())
+        .args(schedule_args())
+        .arg(Arg::new(SEASONAL)
+            .help("Additionally create a schedule for every month or week of the year")
+            .long_help("Solar wallpapers only: Additionally create one schedule for every month or week of the year with timings computed for that period. All schedules share the same images, an index describing which schedule applies to which dates is written alongside. Requires --latitude and --longitude.")
+            .long("seasonal")
+            .num_args(1)
+            .value_name("PERIOD")
+            .value_parser(["month", "week"])
+            .requires(LAT)
         )
-        .get_matches();
-    
-    if matches.contains_id(VERS) {
-        println!("Version: {}", VERSION.unwrap_or("unknown"));
-        std::process::exit(0);
-    }
+        .arg(Arg::new(FORMAT)
+            .help("Format the images are extracted in")
+            .long_help("Format the images are extracted in. The images referred to by the created descriptions carry the matching extension. Default is png.")
+            .short('f')
+            .long("format")
+            .num_args(1)
+            .value_name(FORMAT)
+            .value_parser(["png", "jpeg", "webp", "avif"])
+        )
+        .arg(Arg::new(DEPTH)
+            .help("Bits per color channel of the extracted images")
+            .long_help("Bits per color channel of the extracted images. 16 keeps the precision of wallpapers stored with 10 or 12 bits and avoids banding in gradients, it is only supported by png. Default is 8.")
+            .long("depth")
+            .num_args(1)
+            .value_name(DEPTH)
+            .value_parser(["8", "16"])
+        )
+        .arg(Arg::new(CONVERT_TO_SRGB)
+            .help("Convert the colors of the images to sRGB")
+            .long_help("Convert the colors of the extracted images to sRGB instead of embedding the color profile of the wallpaper, which is often Display P3. Use this for viewers ignoring color profiles and for webp and avif, which are written without profile.")
+            .long("convert-to-srgb")
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new(QUALITY)
+            .help("Quality of the extracted images from 0 to 100, compression level for png")
+            .long_help("Quality of the extracted images from 0 to 100. For png, which is always lossless, this selects the compression level: up to 33 compresses fast, from 67 on the best compression is used. WebP at 100 is lossless. Defaults are 90 for jpeg and webp and 80 for avif.")
+            .short('q')
+            .long("quality")
+            .num_args(1)
+            .value_name(QUALITY)
+            .value_parser(clap::value_parser!(u8).range(0..=100))
+        )
+        .arg(Arg::new(RESIZE)
+            .help("Resolution the images are brought to (WIDTHxHEIGHT)")
+            .long_help("Resolution the extracted images are brought to, formatted as WIDTHxHEIGHT, e.g. 1920x1080. How the images are adjusted is chosen with --resize-mode. Default is the original resolution.")
+            .long("resize")
+            .num_args(1)
+            .value_name("WxH")
+        )
+        .arg(Arg::new(RESIZE_MODE)
+            .help("How images are brought to the resolutions given with --resize or --sizes")
+            .long_help("How images are brought to the resolutions given with --resize or --sizes: fit scales them to fit into it keeping the aspect ratio, fill scales them to cover it and cuts off the overlapping edges, crop cuts out the center without scaling. Default is fill.")
+            .long("resize-mode")
+            .num_args(1)
+            .value_name("MODE")
+            .value_parser(["fit", "fill", "crop"])
+        )
+        .arg(Arg::new(SIZES)
+            .help("Offer every image in several resolutions (WxH,WxH,...)")
+            .long_help("Offer every image in several resolutions, given as comma separated list like 1920x1080,2560x1440,3840x2160. GNOME picks the variant best matching each monitor. Time-based and solar wallpapers only.")
+            .long("sizes")
+            .num_args(1)
+            .value_name("WxH,...")
+            .conflicts_with(RESIZE)
+        )
+        .arg(Arg::new(JOBS)
+            .help("Number of images converted in parallel")
+            .long_help("Number of images decoded and converted in parallel. Default is the number of available CPUs.")
+            .short('j')
+            .long("jobs")
+            .num_args(1)
+            .value_name("N")
+            .value_parser(clap::value_parser!(u16).range(1..))
+        )
+        .arg(Arg::new(INSTALL)
+            .help("Register the wallpaper in GNOME Settings")
+            .long_help("Register the converted wallpaper in ~/.local/share/gnome-background-properties so that it is offered in GNOME Settings.")
+            .long("install")
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new(APPLY)
+            .help("Set the wallpaper as background after the conversion")
+            .long_help("Set the converted wallpaper as background of the desktop after the conversion, including the dark style if supported.")
+            .long("apply")
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new(UNINSTALL)
+            .help("Remove a registered wallpaper and its generated files")
+            .long_help("Remove the wallpaper registered with --install under the given name from GNOME Settings, together with all images and descriptions created for it. Images not written by a conversion are kept.")
+            .long("uninstall")
+            .num_args(1)
+            .value_name(NAME)
+            .conflicts_with_all([INPUT, RECURSIVE, INSTALL, APPLY])
+        )
+        .arg(Arg::new(QUIET)
+            .help("Print nothing but errors")
+            .long("quiet")
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new(OUTPUT_FORMAT)
+            .help("How the result is printed")
+            .long_help("How the result is printed. json prints a single JSON object with the detected mode, the created descriptions, all extracted images and the schedule, and no progress. Default is text.")
+            .long("output")
+            .num_args(1)
+            .value_name("FORMAT")
+            .value_parser(["text", "json"])
+        )
+}
 
-    let path = matches
-        .get_one::<String>(INPUT)
-        .ok_or_else(|| anyhow::Error::msg("Could not read INPUT"))?;
+/// Arguments giving the place and date sun positions are computed for.
+fn sun_args() -> [Arg; 3] {
+    [
+        Arg::new(LAT)
+            .help("Latitude used to compute the timings of solar wallpapers")
+            .long_help("Latitude in degrees (north positive) of the place the wallpaper is used at. Together with --longitude this is used to compute at which time of day the sun reaches the position depicted in each image of a solar wallpaper.")
+            .long("latitude")
+            .num_args(1)
+            .value_name(LAT)
+            .allow_negative_numbers(true)
+            .value_parser(clap::value_parser!(f64))
+            .requires(LON),
+        Arg::new(LON)
+            .help("Longitude used to compute the timings of solar wallpapers")
+            .long_help("Longitude in degrees (east positive) of the place the wallpaper is used at. Required together with --latitude.")
+            .long("longitude")
+            .num_args(1)
+            .value_name(LON)
+            .allow_negative_numbers(true)
+            .value_parser(clap::value_parser!(f64))
+            .requires(LAT),
+        Arg::new(DATE)
+            .help("Date for which the sun positions are computed (YYYY-MM-DD)")
+            .long_help("Date for which the sun positions of solar wallpapers are computed, formatted as YYYY-MM-DD. Default is today.")
+            .long("date")
+            .num_args(1)
+            .value_name(DATE),
+    ]
+}
 
-    let name = if matches.contains_id(NAME) {
-        matches.get_one::<String>(NAME).unwrap().trim()
-    }
-    else {
-        let path = Path::new(path);
-        path.file_stem().unwrap().to_str().unwrap()
-    };
+fn schedule_args() -> [Arg; 3] {
+    [
+        Arg::new(OFFSET)
+            .help("Shift the whole schedule (e.g. +3h, -1h30m)")
+            .long_help("Shift the times at which all images are shown by the given duration, e.g. +3h, -1h30m or 45m. Applied after --anchor.")
+            .long("offset")
+            .num_args(1)
+            .value_name(OFFSET)
+            .allow_hyphen_values(true),
+        Arg::new(ANCHOR)
+            .help("Show an image at a fixed time of day (IMAGE@HH:MM)")
+            .long_help("Show the image with the given index at the given time of day, formatted as IMAGE@HH:MM, e.g. 5@12:00 to show image 5 at noon. The rest of the schedule is shifted along with it. The indices of the images are listed by the inspect subcommand.")
+            .long("anchor")
+            .num_args(1)
+            .value_name("IMAGE@HH:MM"),
+        Arg::new(STRETCH_DAY)
+            .help("Stretch the daylight images relative to the night images")
+            .long_help("Stretch the part of the day shown with daylight images by the given factor around its middle, the night images share the remaining time. 1.5 makes the day half as long again, 0.5 halves it. Daylight images are those of the light style, for time-based wallpapers those shown between 06:00 and 18:00.")
+            .long("stretch-day")
+            .num_args(1)
+            .value_name("FACTOR")
+            .value_parser(clap::value_parser!(f32)),
+    ]
+}
 
-    let parent_directory = if matches.contains_id(DIR) {
-        let nu_path = std::path::Path::new(matches.get_one::<String>(DIR).unwrap().trim()).to_path_buf();
-        if !nu_path.exists() {
-            std::fs::create_dir_all(&nu_path)?
-        }
-        nu_path.canonicalize()?
-    } else {
-        let mut path = std::path::Path::new(path)
-            .canonicalize()
-            .map_err(|e| {
-                anyhow::Error::msg(format!("Cannot get absolute path of the given file: {}", e))
-            })?
-            .ancestors()
-            .nth(1)
-            .ok_or_else(|| {
-                anyhow::Error::msg(format!(
-                    "Cannot get parent of given image path: \"{}\"",
-                    path
+fn read_adjustment(matches: &ArgMatches) -> Result<Adjustment> {
+    Ok(Adjustment {
+        day_stretch: match matches.get_one::<f32>(STRETCH_DAY) {
+            Some(&factor) if factor <= 0.0 => {
+                return Err(Error::InvalidArgument(format!(
+                    "Invalid factor {} for stretching the day, it must be greater than 0",
+                    factor
                 ))
-            })?.to_path_buf();
-        path.push(name);
+                .into())
+            }
+            factor => factor.copied(),
+        },
+        anchor: matches
+            .get_one::<String>(ANCHOR)
+            .map(|anchor| Anchor::parse(anchor))
+            .transpose()?,
+        offset: matches
+            .get_one::<String>(OFFSET)
+            .map(|offset| Adjustment::parse_offset(offset))
+            .transpose()?
+            .unwrap_or(0.0),
+    })
+}
 
-        if !path.exists() {
-            std::fs::create_dir_all(&path)?
+fn read_location(matches: &ArgMatches) -> Result<Option<Location>> {
+    match (matches.get_one::<f64>(LAT), matches.get_one::<f64>(LON)) {
+        (Some(&latitude), Some(&longitude)) => {
+            if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+                return Err(Error::InvalidArgument(format!(
+                    "Invalid location {}, {}: Latitude must be within [-90, 90] and longitude within [-180, 180]",
+                    latitude, longitude
+                ))
+                .into());
+            }
+            Ok(Some(Location {
+                latitude,
+                longitude,
+            }))
         }
+        _ => Ok(None),
+    }
+}
 
-        path
-    };
-    let image_ctx = HeifContext::read_from_file(path)?;
-
-    // FETCH file wide metadata
-    println!(
-        "{}: Fetch metadata from image...",
-        "Preparation".bright_blue(),
-    );
-    let base64plist = metadata::get_wallpaper_metadata(&image_ctx);
-
-    if base64plist.is_none() {
-        return Err(anyhow::Error::msg("No valid metadata found describing wallpaper! Please check if the mime field is available and carries an apple_desktop:h24 and/or apple_desktop:solar value"));
+fn read_date(matches: &ArgMatches) -> Result<NaiveDate> {
+    match matches.get_one::<String>(DATE) {
+        Some(date) => NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").map_err(|e| {
+            Error::InvalidArgument(format!(
+                "Invalid date \"{}\", expected YYYY-MM-DD: {}",
+                date, e
+            ))
+            .into()
+        }),
+        None => Ok(chrono::Local::now().date_naive()),
     }
+}
 
-    // let image_name = Path::new(path)
-    //     .file_stem()
-    //     .expect("Could not get file name of path")
-    //     .to_string_lossy();
+fn run_inspect(matches: &ArgMatches) -> Result<()> {
+    let path = matches
+        .get_one::<String>(INPUT)
+        .ok_or_else(|| anyhow::Error::msg("Could not read INPUT"))?;
+    let options = Options {
+        location: read_location(matches)?,
+        date: read_date(matches)?,
+        adjustment: read_adjustment(matches)?,
+        ..Options::default()
+    };
+    let report = inspect::inspect(path, &options)?;
+    if matches.get_flag(JSON) {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
 
-    println!(
-        "{}: Detecting wallpaper description type...",
-        "Preparation".bright_blue(),
-    );
-    match base64plist.unwrap() {
-        metadata::WallPaperMode::H24(content) => {
-            println!(
-                "{}: Detected time-based wallpaper.",
-                "Preparation".bright_blue(),
-            );
-            timebased::compute_time_based_wallpaper(
-                image_ctx,
-                content,
-                &parent_directory,
-                &name,
-            )
-        }
-        metadata::WallPaperMode::Solar(content) => {
+    println!("{}: {}", "Mode".bright_blue(), report.mode.as_deref().unwrap_or("unknown"));
+    println!("{}: {}", "Images".bright_blue(), report.images.len());
+    for image in report.images.iter() {
+        let profile = match &image.color_profile {
+            Some(ColorProfile::Icc { size }) => format!("ICC profile of {} bytes", size),
+            Some(ColorProfile::Nclx {
+                color_primaries,
+                transfer_characteristics,
+                matrix_coefficients,
+                full_range,
+            }) => format!(
+                "nclx primaries {}, transfer {}, matrix {}, {} range",
+                color_primaries,
+                transfer_characteristics,
+                matrix_coefficients,
+                if *full_range { "full" } else { "limited" }
+            ),
+            None => "no color profile".to_string(),
+        };
+        println!(
+            "  {}: {}x{}, {} bit{}{}, {}",
+            image.index,
+            image.width,
+            image.height,
+            image.bits_per_pixel,
+            if image.alpha { ", alpha" } else { "" },
+            if image.primary { ", primary" } else { "" },
+            profile
+        );
+    }
+    for xmp in report.xmp.iter() {
+        println!(
+            "{}:\n{}",
+            format!("XMP of image {}", xmp.image).bright_blue(),
+            xmp.content.trim()
+        );
+    }
+    if let Some(plist) = &report.plist {
+        println!("{}:", "Plist".bright_blue());
+        plist.to_writer_xml(std::io::stdout())?;
+        println!();
+    }
+    if !report.schedule.is_empty() {
+        println!("{}:", "Schedule".bright_blue());
+        for entry in report.schedule.iter() {
             println!(
-                "{}: Detected solar-based wallpaper.",
-                "Preparation".bright_blue(),
+                "  {} image {}{}",
+                entry.time,
+                entry.image,
+                if entry.dark { " (dark)" } else { "" }
             );
-            solar::compute_solar_based_wallpaper(
-                image_ctx, 
-                content, 
-                &parent_directory, 
-                &name
-            )
         }
     }
+    if let Some(error) = &report.error {
+        println!("{}: {}", "Error".red(), error);
+    }
+    Ok(())
 }
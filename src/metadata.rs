@@ -13,85 +13,350 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
-use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::Cursor;
 use libheif_rs::HeifContext;
-use quick_xml::{events::Event, Reader};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::name::{LocalName, Namespace, ResolveResult};
+use quick_xml::reader::NsReader;
 use base64::{Engine as _, engine::general_purpose};
 
-use crate::schema::plist::{WallpaperMetaSun, WallpaperMetaTime};
+use crate::error::Error;
+use crate::schema::plist::{WallpaperMetaAppearance, WallpaperMetaSun, WallpaperMetaTime};
 
+/// Names of the modes in the `apple_desktop` namespace
+const H24: &str = "h24";
+const SOLAR: &str = "solar";
+const APPEARANCE: &str = "apr";
+
+/// Base64 encoded plist found in the image metadata
 pub enum WallPaperMode {
     H24(String),
     Solar(String),
+    Appearance(String),
+}
+
+impl WallPaperMode {
+    /// Name of the mode in the `apple_desktop` namespace
+    pub fn name(&self) -> &'static str {
+        match self {
+            WallPaperMode::H24(_) => H24,
+            WallPaperMode::Solar(_) => SOLAR,
+            WallPaperMode::Appearance(_) => APPEARANCE,
+        }
+    }
+
+    /// The plist with all of its fields, including those not used for the
+    /// conversion.
+    pub fn plist(&self) -> Result<plist::Value, Error> {
+        let content = match self {
+            WallPaperMode::H24(content)
+            | WallPaperMode::Solar(content)
+            | WallPaperMode::Appearance(content) => content,
+        };
+        let decoded = general_purpose::STANDARD
+            .decode(content)
+            .map_err(|e| Error::CorruptPlist(e.to_string()))?;
+        plist::Value::from_reader(Cursor::new(decoded))
+            .map_err(|e| Error::CorruptPlist(e.to_string()))
+    }
+
+    pub fn decode(&self) -> Result<WallpaperMeta, Error> {
+        Ok(match self {
+            WallPaperMode::H24(content) => WallpaperMeta::Time(get_time_plist_from_base64(content)?),
+            WallPaperMode::Solar(content) => {
+                WallpaperMeta::Solar(get_solar_plist_from_base64(content)?)
+            }
+            WallPaperMode::Appearance(content) => {
+                WallpaperMeta::Appearance(get_appearance_plist_from_base64(content)?)
+            }
+        })
+    }
+}
+
+/// Decoded description of how the images of a wallpaper are to be shown
+#[derive(Debug)]
+pub enum WallpaperMeta {
+    Time(WallpaperMetaTime),
+    Solar(WallpaperMetaSun),
+    Appearance(WallpaperMetaAppearance),
+}
+
+impl WallpaperMeta {
+    /// See [`WallPaperMode::name`]
+    pub fn name(&self) -> &'static str {
+        match self {
+            WallpaperMeta::Time(_) => H24,
+            WallpaperMeta::Solar(_) => SOLAR,
+            WallpaperMeta::Appearance(_) => APPEARANCE,
+        }
+    }
+}
+
+/// Namespace the description of a wallpaper is stored in, usually bound to
+/// the `apple_desktop` prefix.
+pub const NAMESPACE: &str = "http://ns.apple.com/namespace/1.0/";
+
+/// An XMP metadata block and the image it belongs to.
+#[derive(Debug, Serialize)]
+pub struct Xmp {
+    /// Position of the image among the top level images of the container
+    pub image: usize,
+    pub content: String,
 }
 
-pub fn get_wallpaper_metadata(image_ctx: &HeifContext) -> Option<WallPaperMode> {
-    // Fetch META information about all images (These are by standard stored in the first images meta information tags)
-    let mut metadatas = vec![0; 1];
-    image_ctx
-        .primary_image_handle()
-        .unwrap()
-        .metadata_block_ids( &mut metadatas, b"mime");
-    let metadata_id = metadatas
-        .get(0)
-        .expect("Could not get metadata information");
-    let base64plist = {
-        let tmp = image_ctx
-            .primary_image_handle()
-            .unwrap()
-            .metadata(*metadata_id)
-            .unwrap();
-        let content = String::from_utf8_lossy(&tmp);
-        //println!("{:?}", content);
-        let mut reader = Reader::from_str(&content);
-        reader.trim_text(true);
-
-        // let mut buf = Vec::new();
-        let mut h24 = None;
-
-        loop {
-            match reader.read_event() {
-                Ok(quick_xml::events::Event::Empty(ref e)) => {
-                    e.attributes()
-                        .filter(|att| {
-                            att.as_ref().unwrap().key.0 == "apple_desktop:h24".as_bytes()
-                                || att.as_ref().unwrap().key.0 == "apple_desktop:solar".as_bytes()
-                        })
-                        .for_each(|att| match att.as_ref().unwrap().key {
-                            s if s.0 == "apple_desktop:h24".as_bytes() => {
-                                h24 = Some(WallPaperMode::H24(
-                                    String::from_utf8_lossy(&att.unwrap().value)
-                                        .to_string(),
-                                ))
-                            }
-                            s if s.0 == "apple_desktop:solar".as_bytes() => {
-                                h24 = Some(WallPaperMode::Solar(
-                                    String::from_utf8_lossy(&att.unwrap().value)
-                                        .to_string(),
-                                ))
-                            }
-                            _ => panic!("Invalid Branch"),
-                        });
-                    break;
+/// Content of all XMP metadata blocks, those of the primary image first,
+/// followed by those of the other images. Blocks which cannot be read are
+/// left out.
+pub fn raw_xmp(image_ctx: &HeifContext) -> Vec<Xmp> {
+    let mut image_ids = vec![0; image_ctx.number_of_top_level_images()];
+    image_ctx.top_level_image_ids(&mut image_ids);
+    let mut handles: Vec<_> = image_ids
+        .into_iter()
+        .enumerate()
+        .filter_map(|(image, id)| Some((image, image_ctx.image_handle(id).ok()?)))
+        .collect();
+    handles.sort_by_key(|(_, handle)| !handle.is_primary());
+
+    let mut blocks = Vec::new();
+    for (image, handle) in handles {
+        let mut ids = vec![0; handle.number_of_metadata_blocks(b"mime").max(0) as usize];
+        let count = handle.metadata_block_ids(&mut ids, b"mime");
+        ids.truncate(count);
+        blocks.extend(
+            ids.into_iter()
+                .filter_map(|id| handle.metadata(id).ok())
+                .map(|data| Xmp {
+                    image,
+                    content: String::from_utf8_lossy(&data).to_string(),
+                }),
+        );
+    }
+    blocks
+}
+
+/// Find the plist describing the wallpaper in the XMP metadata of its images.
+/// The first supported mode found is used, unsupported ones are only reported
+/// if there is none.
+pub fn get_wallpaper_metadata(image_ctx: &HeifContext) -> Result<WallPaperMode, Error> {
+    let mut unsupported = None;
+    for xmp in raw_xmp(image_ctx) {
+        for (name, value) in desktop_properties(&xmp.content) {
+            match name.as_str() {
+                H24 => return Ok(WallPaperMode::H24(value)),
+                SOLAR => return Ok(WallPaperMode::Solar(value)),
+                APPEARANCE => return Ok(WallPaperMode::Appearance(value)),
+                _ => {
+                    unsupported.get_or_insert(name);
                 }
-                Ok(Event::Eof) => break,
-                Err(_) => break,
-                _ => {}
             }
         }
-        h24
-    };
-    base64plist
+    }
+    Err(unsupported.map_or(Error::NoMetadata, Error::UnsupportedMode))
 }
 
-pub fn get_time_plist_from_base64(input: &str) -> Result<WallpaperMetaTime> {
-    let decoded = general_purpose::STANDARD.decode(input)?;
-    let plist = plist::from_bytes(&decoded)?;
-    Ok(plist)
+/// Local name and value of all properties in [`NAMESPACE`] of an XMP packet.
+/// RDF allows to give them as attribute of an element, e.g. of
+/// `rdf:Description`, or as element of their own with the value as text.
+fn desktop_properties(xmp: &str) -> Vec<(String, String)> {
+    let mut reader = NsReader::from_str(xmp);
+    reader.trim_text(true);
+    let mut properties = Vec::new();
+    // Property given as element whose text is currently read
+    let mut element: Option<(String, String)> = None;
+
+    loop {
+        match reader.read_resolved_event() {
+            Ok((namespace, Event::Start(e))) => {
+                if in_namespace(&namespace) {
+                    element = Some((local_name(e.local_name()), String::new()));
+                }
+                properties.extend(attribute_properties(&reader, &e));
+            }
+            Ok((_, Event::Empty(e))) => properties.extend(attribute_properties(&reader, &e)),
+            Ok((_, Event::Text(text))) => {
+                if let (Some((_, value)), Ok(text)) = (element.as_mut(), text.unescape()) {
+                    value.push_str(&text);
+                }
+            }
+            Ok((_, Event::CData(text))) => {
+                if let Some((_, value)) = element.as_mut() {
+                    value.push_str(&String::from_utf8_lossy(&text));
+                }
+            }
+            Ok((namespace, Event::End(e))) => {
+                let name = local_name(e.local_name());
+                if in_namespace(&namespace)
+                    && element
+                        .as_ref()
+                        .is_some_and(|(current, _)| *current == name)
+                {
+                    let (name, value) = element.take().unwrap_or_default();
+                    properties.push((name, strip_whitespace(&value)));
+                }
+            }
+            Ok((_, Event::Eof)) | Err(_) => break,
+            _ => {}
+        }
+    }
+    properties
+}
+
+fn attribute_properties(reader: &NsReader<&[u8]>, element: &BytesStart) -> Vec<(String, String)> {
+    element
+        .attributes()
+        .flatten()
+        .filter(|attribute| attribute.key.as_namespace_binding().is_none())
+        .filter_map(|attribute| {
+            let (namespace, name) = reader.resolve_attribute(attribute.key);
+            if !in_namespace(&namespace) {
+                return None;
+            }
+            let value = attribute.unescape_value().ok()?;
+            Some((local_name(name), strip_whitespace(&value)))
+        })
+        .collect()
+}
+
+fn in_namespace(namespace: &ResolveResult) -> bool {
+    match namespace {
+        ResolveResult::Bound(Namespace(namespace)) => *namespace == NAMESPACE.as_bytes(),
+        // Tolerate packets using the usual prefix without declaring it
+        ResolveResult::Unknown(prefix) => prefix == b"apple_desktop",
+        ResolveResult::Unbound => false,
+    }
+}
+
+fn local_name(name: LocalName) -> String {
+    String::from_utf8_lossy(name.as_ref()).to_string()
+}
+
+/// Base64 values may be wrapped over several lines.
+fn strip_whitespace(value: &str) -> String {
+    value.split_whitespace().collect()
+}
+
+pub fn get_time_plist_from_base64(input: &str) -> Result<WallpaperMetaTime, Error> {
+    from_base64(input)
+}
+
+pub fn get_solar_plist_from_base64(input: &str) -> Result<WallpaperMetaSun, Error> {
+    from_base64(input)
 }
 
-pub fn get_solar_plist_from_base64(input: &str) -> Result<WallpaperMetaSun> {
-    let decoded = general_purpose::STANDARD.decode(input)?;
-    let plist = plist::from_bytes(&decoded)?;
-    Ok(plist)
+pub fn get_appearance_plist_from_base64(input: &str) -> Result<WallpaperMetaAppearance, Error> {
+    from_base64(input)
+}
+
+fn from_base64<T: DeserializeOwned>(input: &str) -> Result<T, Error> {
+    let decoded = general_purpose::STANDARD
+        .decode(input)
+        .map_err(|e| Error::CorruptPlist(e.to_string()))?;
+    plist::from_bytes(&decoded).map_err(|e| Error::CorruptPlist(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(description: &str) -> String {
+        format!(
+            r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+  <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+    {}
+  </rdf:RDF>
+</x:xmpmeta>"#,
+            description
+        )
+    }
+
+    fn properties(description: &str) -> Vec<(String, String)> {
+        desktop_properties(&packet(description))
+    }
+
+    fn h24(value: &str) -> Vec<(String, String)> {
+        vec![("h24".to_string(), value.to_string())]
+    }
+
+    #[test]
+    fn attribute_form() {
+        assert_eq!(
+            properties(
+                r#"<rdf:Description rdf:about=""
+      xmlns:apple_desktop="http://ns.apple.com/namespace/1.0/"
+      apple_desktop:h24="YnBsaXN0"/>"#
+            ),
+            h24("YnBsaXN0")
+        );
+    }
+
+    #[test]
+    fn element_form() {
+        assert_eq!(
+            properties(
+                r#"<rdf:Description rdf:about=""
+      xmlns:apple_desktop="http://ns.apple.com/namespace/1.0/">
+      <apple_desktop:h24>YnBsaXN0</apple_desktop:h24>
+    </rdf:Description>"#
+            ),
+            h24("YnBsaXN0")
+        );
+    }
+
+    #[test]
+    fn custom_prefix() {
+        assert_eq!(
+            properties(
+                r#"<rdf:Description rdf:about=""
+      xmlns:desk="http://ns.apple.com/namespace/1.0/"
+      desk:h24="YnBsaXN0"/>"#
+            ),
+            h24("YnBsaXN0")
+        );
+    }
+
+    #[test]
+    fn undeclared_prefix() {
+        assert_eq!(
+            properties(r#"<rdf:Description rdf:about="" apple_desktop:h24="YnBsaXN0"/>"#),
+            h24("YnBsaXN0")
+        );
+    }
+
+    #[test]
+    fn wrapped_base64() {
+        assert_eq!(
+            properties(
+                r#"<rdf:Description rdf:about=""
+      xmlns:apple_desktop="http://ns.apple.com/namespace/1.0/">
+      <apple_desktop:h24>
+        YnBs
+        aXN0
+      </apple_desktop:h24>
+    </rdf:Description>"#
+            ),
+            h24("YnBsaXN0")
+        );
+        assert_eq!(
+            properties(
+                r#"<rdf:Description rdf:about=""
+      xmlns:apple_desktop="http://ns.apple.com/namespace/1.0/"
+      apple_desktop:h24="YnBs&#10;  aXN0"/>"#
+            ),
+            h24("YnBsaXN0")
+        );
+    }
+
+    #[test]
+    fn other_namespaces_ignored() {
+        assert!(properties(
+            r#"<rdf:Description rdf:about=""
+      xmlns:other="http://example.com/"
+      other:h24="YnBsaXN0">
+      <other:solar>YnBsaXN0</other:solar>
+    </rdf:Description>"#
+        )
+        .is_empty());
+    }
 }
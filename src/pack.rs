@@ -0,0 +1,523 @@
+// heic-to-dynamic-gnome-wallpaper
+// Copyright (C) 2022 Johannes Wünsche
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::error::Error;
+use crate::parser::GnomeXMLBackgroundParser;
+use crate::progress::{Progress, Stage};
+use crate::schema::manifest::{Manifest, Mode};
+use crate::schema::plist::{
+    Appearance, SolarSlice, TimeSlice, WallpaperMetaSun, WallpaperMetaTime,
+};
+use crate::schema::xml::{Background, Image};
+use crate::util::path;
+use crate::DAY_SECS;
+use anyhow::Result;
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{NaiveTime, Timelike};
+use libheif_rs::{
+    Channel, ColorSpace, CompressionFormat, EncoderQuality, HeifContext, LibHeif, RgbChroma,
+};
+use serde::Serialize;
+use std::io::BufReader;
+use std::path::Path;
+
+const QUALITY: u8 = 90;
+
+pub fn read_background(path: &Path) -> Result<Background> {
+    let file = std::fs::File::open(path).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    GnomeXMLBackgroundParser::new(BufReader::new(file)).parse()
+}
+
+/// Build a time-based dynamic wallpaper from a GNOME slideshow. Every image
+/// shown by the slideshow is stored once in the container, relative paths are
+/// resolved against `base_directory`.
+pub fn pack(
+    background: &Background,
+    base_directory: &Path,
+    output: &Path,
+    progress: &dyn Progress,
+) -> Result<()> {
+    let (files, meta) = time_slices(background, progress)?;
+    if files.is_empty() {
+        return Err(invalid("The slideshow shows no images").into());
+    }
+    write_container(&files, base_directory, "h24", &meta, output, progress)
+}
+
+/// Read a manifest describing a wallpaper, TOML unless the file ends in
+/// `.json`.
+pub fn read_manifest(path: &Path) -> Result<Manifest> {
+    let content = std::fs::read_to_string(path).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let manifest = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_str(&content).map_err(invalid)?,
+        _ => toml::from_str(&content).map_err(invalid)?,
+    };
+    Ok(manifest)
+}
+
+/// Build a dynamic wallpaper from the images listed in a manifest, relative
+/// paths are resolved against `base_directory`.
+pub fn create(
+    manifest: &Manifest,
+    base_directory: &Path,
+    output: &Path,
+    progress: &dyn Progress,
+) -> Result<()> {
+    if manifest.images.is_empty() {
+        return Err(invalid("The manifest lists no images").into());
+    }
+    let files: Vec<String> = manifest
+        .images
+        .iter()
+        .map(|image| image.file.clone())
+        .collect();
+    match manifest.mode {
+        Mode::H24 => {
+            let meta = manifest_time_slices(manifest)?;
+            write_container(&files, base_directory, "h24", &meta, output, progress)
+        }
+        Mode::Solar => {
+            let meta = manifest_solar_slices(manifest)?;
+            write_container(&files, base_directory, "solar", &meta, output, progress)
+        }
+    }
+}
+
+fn manifest_time_slices(manifest: &Manifest) -> Result<WallpaperMetaTime> {
+    let mut time_slices = manifest
+        .images
+        .iter()
+        .enumerate()
+        .map(|(idx, image)| {
+            let time = image
+                .time
+                .as_deref()
+                .ok_or_else(|| invalid(format!("Missing time of \"{}\"", image.file)))?;
+            let time = NaiveTime::parse_from_str(time, "%H:%M:%S")
+                .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M"))
+                .map_err(|_| {
+                    invalid(format!(
+                        "Invalid time \"{}\" of \"{}\", expected HH:MM or HH:MM:SS",
+                        time, image.file
+                    ))
+                })?;
+            Ok(TimeSlice {
+                time: time.num_seconds_from_midnight() as f32 / DAY_SECS,
+                idx,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    time_slices.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(std::cmp::Ordering::Equal));
+
+    let appearance = Appearance {
+        dark: shown_at(&time_slices, 0.0) as i32,
+        light: shown_at(&time_slices, 0.5) as i32,
+    };
+    Ok(WallpaperMetaTime {
+        time_slices,
+        appearance: Some(appearance),
+    })
+}
+
+/// Solar slices of the manifest. Images with the sun below the horizon belong
+/// to the dark style unless stated otherwise, the light and dark appearance
+/// use the images with the highest and lowest sun.
+fn manifest_solar_slices(manifest: &Manifest) -> Result<WallpaperMetaSun> {
+    let solar_slices = manifest
+        .images
+        .iter()
+        .enumerate()
+        .map(|(idx, image)| match (image.altitude, image.azimuth) {
+            (Some(altitude), Some(azimuth)) => Ok(SolarSlice {
+                altitude,
+                idx,
+                light_mode: Some(if image.dark.unwrap_or(altitude < 0.0) {
+                    0
+                } else {
+                    1
+                }),
+                azimuth,
+            }),
+            _ => Err(invalid(format!(
+                "Missing altitude or azimuth of \"{}\"",
+                image.file
+            ))),
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let by_altitude = |a: &&SolarSlice, b: &&SolarSlice| {
+        a.altitude
+            .partial_cmp(&b.altitude)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    };
+    let light = solar_slices.iter().max_by(by_altitude).map(|s| s.idx);
+    let dark = solar_slices.iter().min_by(by_altitude).map(|s| s.idx);
+    Ok(WallpaperMetaSun {
+        appearance: light.zip(dark).map(|(light, dark)| Appearance {
+            dark: dark as i32,
+            light: light as i32,
+        }),
+        solar_slices,
+    })
+}
+
+/// Encode the files in the given order and attach the plist describing them
+/// as `apple_desktop:<kind>` to the primary image, which is the first one.
+fn write_container<M: Serialize>(
+    files: &[String],
+    base_directory: &Path,
+    kind: &str,
+    meta: &M,
+    output: &Path,
+    progress: &dyn Progress,
+) -> Result<()> {
+    let libheif = LibHeif::new();
+    let mut encoder = libheif
+        .encoder_for_format(CompressionFormat::Hevc)
+        .map_err(heif_error)?;
+    encoder
+        .set_quality(EncoderQuality::Lossy(QUALITY))
+        .map_err(heif_error)?;
+    let mut context = HeifContext::new().map_err(heif_error)?;
+
+    progress.message(
+        Stage::Conversion,
+        &format!("Encoding {} images to heic format...", files.len()),
+    );
+    progress.start_images(files.len());
+    let mut primary = None;
+    for file in files.iter() {
+        let path = base_directory.join(file);
+        let image = load_image(&path)?;
+        let handle = context
+            .encode_image(&image, &mut encoder, None)
+            .map_err(heif_error)?;
+        primary.get_or_insert(handle);
+        progress.image_done();
+    }
+    progress.finish_images();
+    let primary = primary.ok_or_else(|| Error::EncodeFailed("No images to encode".to_string()))?;
+
+    progress.message(Stage::Conversion, "Writing wallpaper metadata...");
+    let mut plist = Vec::new();
+    plist::to_writer_binary(&mut plist, meta).map_err(|e| Error::EncodeFailed(e.to_string()))?;
+    let xmp = xmp(kind, &general_purpose::STANDARD.encode(plist));
+    context
+        .add_xmp_metadata(&primary, xmp.as_bytes())
+        .map_err(heif_error)?;
+
+    context
+        .write_to_file(path::to_str(output)?)
+        .map_err(heif_error)?;
+    progress.message(Stage::Conversion, "Done!");
+    Ok(())
+}
+
+/// Distinct files in the order they are first shown, and the time of day
+/// each static image starts at. Slideshows not spanning exactly one day are
+/// stretched to do so. Of images offered in several sizes the largest is used.
+fn time_slices(
+    background: &Background,
+    progress: &dyn Progress,
+) -> Result<(Vec<String>, WallpaperMetaTime)> {
+    let start = background.starttime.hour as f32 * 3600.0
+        + background.starttime.minute as f32 * 60.0
+        + background.starttime.second as f32;
+    let total = background.images.iter().fold(0f32, |acc, image| match image {
+        Image::Static { duration, .. } | Image::Transition { duration, .. } => acc + duration,
+    });
+    if total <= 0.0 {
+        return Err(invalid("The slideshow has no duration").into());
+    }
+    if (total - DAY_SECS).abs() > 1.0 {
+        progress.warning(&format!(
+            "The slideshow lasts {} seconds instead of a day, timings are stretched accordingly.",
+            total
+        ));
+    }
+
+    let mut files: Vec<String> = Vec::new();
+    let mut time_slices = Vec::new();
+    let mut elapsed = 0f32;
+    for image in background.images.iter() {
+        match image {
+            Image::Static { duration, file, .. } => {
+                let file = file.path();
+                let idx = match files.iter().position(|known| known == file) {
+                    Some(idx) => idx,
+                    None => {
+                        files.push(file.to_string());
+                        files.len() - 1
+                    }
+                };
+                time_slices.push(TimeSlice {
+                    time: (start / DAY_SECS + elapsed / total).fract(),
+                    idx,
+                });
+                elapsed += duration;
+            }
+            Image::Transition { duration, .. } => elapsed += duration,
+        }
+    }
+    time_slices.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(std::cmp::Ordering::Equal));
+
+    let appearance = Appearance {
+        dark: shown_at(&time_slices, 0.0) as i32,
+        light: shown_at(&time_slices, 0.5) as i32,
+    };
+    Ok((
+        files,
+        WallpaperMetaTime {
+            time_slices,
+            appearance: Some(appearance),
+        },
+    ))
+}
+
+/// Image shown at the given time of day, slices have to be sorted.
+fn shown_at(time_slices: &[TimeSlice], time: f32) -> usize {
+    time_slices
+        .iter()
+        .rev()
+        .find(|slice| slice.time <= time)
+        .or_else(|| time_slices.last())
+        .map(|slice| slice.idx)
+        .unwrap_or(0)
+}
+
+fn load_image(path: &Path) -> Result<libheif_rs::Image> {
+    let source = image::open(path)
+        .map_err(|e| Error::DecodeFailed(format!("\"{}\": {}", path.display(), e)))?
+        .to_rgb8();
+    let (width, height) = source.dimensions();
+
+    let mut image = libheif_rs::Image::new(width, height, ColorSpace::Rgb(RgbChroma::Rgb))
+        .map_err(heif_error)?;
+    image
+        .create_plane(Channel::Interleaved, width, height, 8)
+        .map_err(heif_error)?;
+    let planes = image.planes_mut();
+    let plane = planes
+        .interleaved
+        .ok_or_else(|| Error::EncodeFailed("Could not create image plane".to_string()))?;
+    let row = width as usize * 3;
+    for (y, source_row) in source.chunks_exact(row).enumerate() {
+        plane.data[y * plane.stride..y * plane.stride + row].copy_from_slice(source_row);
+    }
+    Ok(image)
+}
+
+/// XMP packet carrying a base64 encoded plist as `apple_desktop` attribute.
+fn xmp(kind: &str, content: &str) -> String {
+    format!(
+        r#"<x:xmpmeta xmlns:x="adobe:ns:meta/" x:xmptk="XMP Core 5.4.0">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about="" xmlns:apple_desktop="http://ns.apple.com/namespace/1.0/" apple_desktop:{}="{}"/>
+ </rdf:RDF>
+</x:xmpmeta>"#,
+        kind, content
+    )
+}
+
+fn heif_error(error: libheif_rs::HeifError) -> Error {
+    Error::EncodeFailed(error.to_string())
+}
+
+fn invalid<E: std::fmt::Display>(error: E) -> Error {
+    Error::InvalidDescription(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::Silent;
+    use crate::schema::manifest::ManifestImage;
+    use crate::schema::xml::{File, StartTime};
+
+    const HOUR: f32 = 3600.0;
+
+    /// Slideshow starting at `hour` showing every file for the given hours,
+    /// including the transition to the next one.
+    fn slideshow(hour: u16, shown: &[(&str, f32)]) -> Background {
+        let file = |name: &str| File::Single(name.to_string());
+        Background {
+            starttime: StartTime {
+                year: 2024,
+                month: 6,
+                day: 21,
+                hour,
+                minute: 0,
+                second: 0,
+            },
+            images: shown
+                .iter()
+                .enumerate()
+                .flat_map(|(idx, (name, hours))| {
+                    let next = shown[(idx + 1) % shown.len()].0;
+                    [
+                        Image::Static {
+                            duration: 1.0,
+                            file: file(name),
+                            idx,
+                        },
+                        Image::Transition {
+                            kind: "overlay".to_string(),
+                            duration: hours * HOUR - 1.0,
+                            from: file(name),
+                            to: file(next),
+                            idx,
+                        },
+                    ]
+                })
+                .collect(),
+        }
+    }
+
+    fn slices(meta: &WallpaperMetaTime) -> Vec<(f32, usize)> {
+        meta.time_slices
+            .iter()
+            .map(|slice| ((slice.time * 24.0 * 100.0).round() / 100.0, slice.idx))
+            .collect()
+    }
+
+    fn appearance(appearance: &Option<Appearance>) -> (i32, i32) {
+        let appearance = appearance.as_ref().unwrap();
+        (appearance.dark, appearance.light)
+    }
+
+    fn image(file: &str) -> ManifestImage {
+        ManifestImage {
+            file: file.to_string(),
+            time: None,
+            altitude: None,
+            azimuth: None,
+            dark: None,
+        }
+    }
+
+    #[test]
+    fn slideshow_slices() {
+        let background = slideshow(0, &[("a", 6.0), ("b", 12.0), ("a", 6.0)]);
+        let (files, meta) = time_slices(&background, &Silent).unwrap();
+        assert_eq!(files, ["a", "b"]);
+        assert_eq!(slices(&meta), [(0.0, 0), (6.0, 1), (18.0, 0)]);
+        assert_eq!(appearance(&meta.appearance), (0, 1));
+    }
+
+    #[test]
+    fn slideshow_stretched_to_a_day() {
+        let background = slideshow(0, &[("a", 3.0), ("b", 9.0)]);
+        let (_, meta) = time_slices(&background, &Silent).unwrap();
+        assert_eq!(slices(&meta), [(0.0, 0), (6.0, 1)]);
+    }
+
+    #[test]
+    fn slideshow_starting_late() {
+        let background = slideshow(22, &[("night", 8.0), ("day", 16.0)]);
+        let (files, meta) = time_slices(&background, &Silent).unwrap();
+        assert_eq!(files, ["night", "day"]);
+        assert_eq!(slices(&meta), [(6.0, 1), (22.0, 0)]);
+        assert_eq!(appearance(&meta.appearance), (0, 1));
+    }
+
+    #[test]
+    fn slideshow_without_duration() {
+        assert!(time_slices(&slideshow(0, &[("a", 0.0)]), &Silent).is_err());
+    }
+
+    #[test]
+    fn shown_at_wraps() {
+        let slices = [
+            TimeSlice { time: 0.25, idx: 1 },
+            TimeSlice { time: 0.75, idx: 2 },
+        ];
+        assert_eq!(shown_at(&slices, 0.0), 2);
+        assert_eq!(shown_at(&slices, 0.25), 1);
+        assert_eq!(shown_at(&slices, 0.5), 1);
+        assert_eq!(shown_at(&[], 0.5), 0);
+    }
+
+    #[test]
+    fn manifest_times() {
+        let times = ["18:00", "06:00:36", "12:00"];
+        let manifest = Manifest {
+            mode: Mode::H24,
+            images: times
+                .iter()
+                .map(|time| ManifestImage {
+                    time: Some(time.to_string()),
+                    ..image(time)
+                })
+                .collect(),
+        };
+        let meta = manifest_time_slices(&manifest).unwrap();
+        assert_eq!(slices(&meta), [(6.01, 1), (12.0, 2), (18.0, 0)]);
+        // Night at midnight, day at noon
+        assert_eq!(appearance(&meta.appearance), (0, 2));
+
+        for time in [None, Some("25:00".to_string()), Some("noon".to_string())] {
+            let manifest = Manifest {
+                mode: Mode::H24,
+                images: vec![ManifestImage {
+                    time,
+                    ..image("a.png")
+                }],
+            };
+            assert!(manifest_time_slices(&manifest).is_err());
+        }
+    }
+
+    #[test]
+    fn manifest_sun_positions() {
+        let position = |altitude, dark| ManifestImage {
+            altitude: Some(altitude),
+            azimuth: Some(90.0),
+            dark,
+            ..image("a.png")
+        };
+        let manifest = Manifest {
+            mode: Mode::Solar,
+            images: vec![
+                position(-10.0, None),
+                position(60.0, None),
+                position(5.0, Some(true)),
+                position(-2.0, Some(false)),
+            ],
+        };
+        let meta = manifest_solar_slices(&manifest).unwrap();
+        let light_modes: Vec<Option<usize>> = meta
+            .solar_slices
+            .iter()
+            .map(|slice| slice.light_mode)
+            .collect();
+        assert_eq!(light_modes, [Some(0), Some(1), Some(0), Some(1)]);
+        assert_eq!(appearance(&meta.appearance), (0, 1));
+
+        let manifest = Manifest {
+            mode: Mode::Solar,
+            images: vec![ManifestImage {
+                altitude: Some(10.0),
+                ..image("a.png")
+            }],
+        };
+        assert!(manifest_solar_slices(&manifest).is_err());
+    }
+}
@@ -0,0 +1,399 @@
+// heic-to-dynamic-gnome-wallpaper
+// Copyright (C) 2022 Johannes Wünsche
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::error::Error;
+use crate::schema::xml::{Background, File, Image, Size, StartTime, Wallpaper, Wallpapers};
+use anyhow::Result;
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader,
+};
+use std::collections::HashMap;
+use std::io::BufRead;
+
+/// Reads GNOME slideshow descriptions as written by
+/// [`GnomeXMLBackgroundSerializer`](crate::serializer::GnomeXMLBackgroundSerializer)
+/// or by hand, including files offered in several `<size>`s. Serializing the
+/// parsed [`Background`] again yields an equal description.
+pub struct GnomeXMLBackgroundParser<T: BufRead> {
+    reader: Reader<T>,
+}
+
+/// Values of a `<static>` or `<transition>` element collected while reading
+#[derive(Default)]
+struct Entry {
+    kind: Option<String>,
+    duration: Option<f32>,
+    file: Option<File>,
+    from: Option<File>,
+    to: Option<File>,
+}
+
+impl Entry {
+    fn field(&mut self, name: &str) -> Option<&mut Option<File>> {
+        match name {
+            "file" => Some(&mut self.file),
+            "from" => Some(&mut self.from),
+            "to" => Some(&mut self.to),
+            _ => None,
+        }
+    }
+}
+
+impl<T> GnomeXMLBackgroundParser<T>
+where
+    T: BufRead,
+{
+    pub fn new(reader: T) -> Self {
+        let mut reader = Reader::from_reader(reader);
+        reader.trim_text(true);
+        Self { reader }
+    }
+
+    pub fn parse(&mut self) -> Result<Background> {
+        let mut buf = Vec::new();
+        let mut path: Vec<String> = Vec::new();
+        let mut starttime = StartTime {
+            year: 0,
+            month: 1,
+            day: 1,
+            hour: 0,
+            minute: 0,
+            second: 0,
+        };
+        let mut images = Vec::new();
+        let mut entry = Entry::default();
+        // Dimensions of the `<size>` element currently read
+        let mut size = None;
+        // Statics and the transition following them share an index
+        let mut idx = 0;
+
+        loop {
+            match self.reader.read_event_into(&mut buf).map_err(invalid)? {
+                Event::Start(e) => {
+                    let name = String::from_utf8_lossy(e.name().as_ref()).to_string();
+                    match name.as_str() {
+                        "transition" => entry.kind = attribute(&e, "type")?,
+                        "size" => size = Some(dimensions(&e)?),
+                        _ => {}
+                    }
+                    path.push(name);
+                }
+                Event::End(_) => match path.pop().as_deref() {
+                    Some("static") => {
+                        let entry = std::mem::take(&mut entry);
+                        images.push(Image::Static {
+                            duration: required(entry.duration, "duration", "static")?,
+                            file: required(entry.file, "file", "static")?,
+                            idx,
+                        });
+                    }
+                    Some("transition") => {
+                        let entry = std::mem::take(&mut entry);
+                        images.push(Image::Transition {
+                            kind: entry.kind.unwrap_or_else(|| "overlay".to_string()),
+                            duration: required(entry.duration, "duration", "transition")?,
+                            from: required(entry.from, "from", "transition")?,
+                            to: required(entry.to, "to", "transition")?,
+                            idx,
+                        });
+                        idx += 1;
+                    }
+                    _ => {}
+                },
+                Event::Text(e) => {
+                    let text = e.unescape().map_err(invalid)?.to_string();
+                    let parent = path.iter().rev().nth(1).map(String::as_str);
+                    match (parent, path.last().map(String::as_str)) {
+                        (Some("starttime"), Some(field)) => {
+                            let value = || {
+                                text.parse::<u32>().map_err(|_| {
+                                    invalid(format!(
+                                        "Invalid value \"{}\" in <{}> of <starttime>",
+                                        text, field
+                                    ))
+                                })
+                            };
+                            match field {
+                                "year" => starttime.year = value()? as i32,
+                                "month" => starttime.month = value()?,
+                                "day" => starttime.day = value()?,
+                                "hour" => starttime.hour = value()? as u16,
+                                "minute" => starttime.minute = value()? as u16,
+                                "second" => starttime.second = value()? as u16,
+                                _ => {}
+                            }
+                        }
+                        (_, Some("duration")) => {
+                            entry.duration =
+                                Some(text.parse().map_err(|_| {
+                                    invalid(format!("Invalid duration \"{}\"", text))
+                                })?)
+                        }
+                        (Some(field), Some("size")) => {
+                            let (width, height) = size
+                                .take()
+                                .ok_or_else(|| invalid("Missing dimensions of <size>"))?;
+                            let size = Size {
+                                width,
+                                height,
+                                file: text,
+                            };
+                            match entry.field(field) {
+                                Some(Some(File::Sizes(sizes))) => sizes.push(size),
+                                Some(value) => *value = Some(File::Sizes(vec![size])),
+                                None => {}
+                            }
+                        }
+                        (_, Some(field)) => {
+                            if let Some(value) = entry.field(field) {
+                                *value = Some(File::Single(text));
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+
+        Ok(Background { starttime, images })
+    }
+}
+
+/// Reads `gnome-background-properties` entries as written by
+/// [`GnomeXMLPropertiesSerializer`](crate::serializer::GnomeXMLPropertiesSerializer).
+pub struct GnomeXMLPropertiesParser<T: BufRead> {
+    reader: Reader<T>,
+}
+
+impl<T> GnomeXMLPropertiesParser<T>
+where
+    T: BufRead,
+{
+    pub fn new(reader: T) -> Self {
+        let mut reader = Reader::from_reader(reader);
+        reader.trim_text(true);
+        Self { reader }
+    }
+
+    pub fn parse(&mut self) -> Result<Wallpapers> {
+        let mut buf = Vec::new();
+        let mut path: Vec<String> = Vec::new();
+        let mut wallpapers = Vec::new();
+        let mut fields: HashMap<String, String> = HashMap::new();
+
+        loop {
+            match self.reader.read_event_into(&mut buf).map_err(invalid)? {
+                Event::Start(e) => {
+                    path.push(String::from_utf8_lossy(e.name().as_ref()).to_string());
+                }
+                Event::End(_) => {
+                    let element = path.pop();
+                    if element.as_deref() == Some("wallpaper") {
+                        let mut fields = std::mem::take(&mut fields);
+                        let mut field = |name: &str| {
+                            required(fields.remove(name), name, "wallpaper")
+                        };
+                        wallpapers.push(Wallpaper {
+                            name: field("name")?,
+                            filename: field("filename")?,
+                            filename_dark: field("filename-dark").ok(),
+                            options: field("options").unwrap_or_else(|_| "zoom".to_string()),
+                            shade_type: field("shade_type")
+                                .unwrap_or_else(|_| "solid".to_string()),
+                        });
+                    }
+                }
+                Event::Text(e) => {
+                    let parent = path.iter().rev().nth(1).map(String::as_str);
+                    if let (Some("wallpaper"), Some(field)) = (parent, path.last()) {
+                        fields.insert(field.clone(), e.unescape().map_err(invalid)?.to_string());
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+
+        Ok(Wallpapers { wallpapers })
+    }
+}
+
+fn required<V>(value: Option<V>, field: &str, element: &str) -> Result<V> {
+    value.ok_or_else(|| invalid(format!("Missing <{}> in <{}>", field, element)).into())
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Result<Option<String>> {
+    Ok(element
+        .try_get_attribute(name)
+        .map_err(invalid)?
+        .map(|value| value.unescape_value().map(|v| v.to_string()))
+        .transpose()
+        .map_err(invalid)?)
+}
+
+fn dimensions(element: &BytesStart) -> Result<(u32, u32)> {
+    let dimension = |name: &str| -> Result<u32> {
+        let value = required(attribute(element, name)?, name, "size")?;
+        value.parse().map_err(|_| {
+            invalid(format!("Invalid value \"{}\" of {} in <size>", value, name)).into()
+        })
+    };
+    Ok((dimension("width")?, dimension("height")?))
+}
+
+fn invalid<E: std::fmt::Display>(error: E) -> Error {
+    Error::InvalidDescription(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::xml::Size;
+    use crate::serializer::GnomeXMLBackgroundSerializer;
+
+    fn parse(xml: &[u8]) -> Background {
+        GnomeXMLBackgroundParser::new(xml).parse().unwrap()
+    }
+
+    fn sizes(name: &str) -> File {
+        File::Sizes(vec![
+            Size {
+                width: 1920,
+                height: 1080,
+                file: format!("/tmp/{}-1920x1080.png", name),
+            },
+            Size {
+                width: 3840,
+                height: 2160,
+                file: format!("/tmp/{}-3840x2160.png", name),
+            },
+        ])
+    }
+
+    #[test]
+    fn round_trip() {
+        let background = Background {
+            starttime: StartTime {
+                year: 2024,
+                month: 6,
+                day: 21,
+                hour: 19,
+                minute: 30,
+                second: 5,
+            },
+            images: vec![
+                Image::Static {
+                    duration: 1.0,
+                    file: File::Single("/tmp/0.png".to_string()),
+                    idx: 0,
+                },
+                Image::Transition {
+                    kind: "overlay".to_string(),
+                    duration: 43199.5,
+                    from: File::Single("/tmp/0.png".to_string()),
+                    to: sizes("1"),
+                    idx: 0,
+                },
+                Image::Static {
+                    duration: 1.0,
+                    file: sizes("1"),
+                    idx: 1,
+                },
+                Image::Transition {
+                    kind: "overlay".to_string(),
+                    duration: 43199.0,
+                    from: sizes("1"),
+                    to: File::Single("/tmp/0.png".to_string()),
+                    idx: 1,
+                },
+            ],
+        };
+        let mut xml = Vec::new();
+        GnomeXMLBackgroundSerializer::new(&mut xml)
+            .serialize(&background)
+            .unwrap();
+        assert_eq!(parse(&xml), background);
+    }
+
+    #[test]
+    fn hand_written() {
+        let xml = br#"<?xml version="1.0"?>
+<background>
+  <starttime>
+    <year>2011</year><month>11</month><day>24</day>
+    <hour>7</hour><minute>0</minute><second>0</second>
+  </starttime>
+  <!-- Morning -->
+  <static>
+    <duration>1795.0</duration>
+    <file>/usr/share/backgrounds/morning.jpg</file>
+  </static>
+  <transition type="overlay">
+    <duration>5.0</duration>
+    <from>/usr/share/backgrounds/morning.jpg</from>
+    <to>
+      <size width="1024" height="768">/usr/share/backgrounds/night-1024.jpg</size>
+      <size width="1920" height="1200">/usr/share/backgrounds/night-1920.jpg</size>
+    </to>
+  </transition>
+</background>
+"#;
+        let background = parse(xml);
+        assert_eq!(
+            background.starttime,
+            StartTime {
+                year: 2011,
+                month: 11,
+                day: 24,
+                hour: 7,
+                minute: 0,
+                second: 0,
+            }
+        );
+        assert_eq!(
+            background.images,
+            vec![
+                Image::Static {
+                    duration: 1795.0,
+                    file: File::Single("/usr/share/backgrounds/morning.jpg".to_string()),
+                    idx: 0,
+                },
+                Image::Transition {
+                    kind: "overlay".to_string(),
+                    duration: 5.0,
+                    from: File::Single("/usr/share/backgrounds/morning.jpg".to_string()),
+                    to: File::Sizes(vec![
+                        Size {
+                            width: 1024,
+                            height: 768,
+                            file: "/usr/share/backgrounds/night-1024.jpg".to_string(),
+                        },
+                        Size {
+                            width: 1920,
+                            height: 1200,
+                            file: "/usr/share/backgrounds/night-1920.jpg".to_string(),
+                        },
+                    ]),
+                    idx: 0,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,45 @@
+// heic-to-dynamic-gnome-wallpaper
+// Copyright (C) 2022 Johannes Wünsche
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/// Steps of a conversion, used to label progress messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Preparation,
+    Conversion,
+}
+
+/// Receives progress information during a conversion. Images are extracted on
+/// several threads, which report to the same receiver.
+pub trait Progress: Sync {
+    fn message(&self, stage: Stage, message: &str);
+    /// Something went wrong without stopping the conversion.
+    fn warning(&self, message: &str);
+    /// `total` images are about to be extracted.
+    fn start_images(&self, total: usize);
+    fn image_done(&self);
+    fn finish_images(&self);
+}
+
+/// Discards all progress information.
+pub struct Silent;
+
+impl Progress for Silent {
+    fn message(&self, _stage: Stage, _message: &str) {}
+    fn warning(&self, _message: &str) {}
+    fn start_images(&self, _total: usize) {}
+    fn image_done(&self) {}
+    fn finish_images(&self) {}
+}
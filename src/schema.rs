@@ -13,5 +13,7 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
+pub mod index;
+pub mod manifest;
 pub mod plist;
 pub mod xml;
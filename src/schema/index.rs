@@ -0,0 +1,34 @@
+// heic-to-dynamic-gnome-wallpaper
+// Copyright (C) 2022 Johannes Wünsche
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+/// Lists which of the seasonal schedules of a wallpaper applies to which
+/// range of dates, both ends inclusive.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SeasonIndex {
+    pub name: String,
+    pub seasons: Vec<SeasonEntry>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SeasonEntry {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub file: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_dark: Option<String>,
+}
@@ -0,0 +1,55 @@
+// heic-to-dynamic-gnome-wallpaper
+// Copyright (C) 2022 Johannes Wünsche
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use serde::Deserialize;
+
+/// Description of a wallpaper to create from a directory of images, read
+/// from a TOML or JSON file.
+///
+/// ```toml
+/// mode = "solar"
+///
+/// [[images]]
+/// file = "sunrise.jpg"
+/// altitude = -0.5
+/// azimuth = 95.0
+/// ```
+#[derive(Deserialize, Debug)]
+pub struct Manifest {
+    pub mode: Mode,
+    pub images: Vec<ManifestImage>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    H24,
+    Solar,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ManifestImage {
+    /// Path of the image, relative to the directory
+    pub file: String,
+    /// Time of day the image is shown at as `HH:MM` or `HH:MM:SS`, used by
+    /// time-based wallpapers
+    pub time: Option<String>,
+    /// Sun position shown in the image in degrees, used by solar wallpapers
+    pub altitude: Option<f32>,
+    pub azimuth: Option<f32>,
+    /// Whether the image belongs to the dark style, by default images with the
+    /// sun below the horizon do
+    pub dark: Option<bool>,
+}
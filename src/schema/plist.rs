@@ -13,17 +13,18 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub struct WallpaperMetaTime {
     #[serde(rename = "ti")]
     pub time_slices: Vec<TimeSlice>,
-    #[serde(rename = "ap")]
-    pub appearance: Appearance,
+    #[serde(rename = "ap", default, skip_serializing_if = "Option::is_none")]
+    pub appearance: Option<Appearance>,
 }
 
-#[derive(Deserialize, Debug)]
+/// Indices of the images to show when a static light or dark style is used.
+#[derive(Deserialize, Serialize, Debug)]
 pub struct Appearance {
     #[serde(rename = "d")]
     pub dark: i32,
@@ -31,7 +32,7 @@ pub struct Appearance {
     pub light: i32,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub struct TimeSlice {
     #[serde(rename = "t")]
     pub time: f32,
@@ -39,22 +40,31 @@ pub struct TimeSlice {
     pub idx: usize,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub struct WallpaperMetaSun {
     #[serde(rename = "si")]
     pub solar_slices: Vec<SolarSlice>,
-    // #[serde(rename = "ap")]
-    // pub appearance: Appearance,
+    #[serde(rename = "ap", default, skip_serializing_if = "Option::is_none")]
+    pub appearance: Option<Appearance>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub struct SolarSlice {
     #[serde(rename = "a")]
     pub altitude: f32,
     #[serde(rename = "i")]
     pub idx: usize,
-    // #[serde(rename = "o")]
-    // pub light_mode: usize,
+    /// `1` for frames belonging to the light style, `0` for the dark style
+    #[serde(rename = "o", default, skip_serializing_if = "Option::is_none")]
+    pub light_mode: Option<usize>,
     #[serde(rename = "z")]
     pub azimuth: f32,
 }
+
+#[derive(Deserialize, Debug)]
+pub struct WallpaperMetaAppearance {
+    #[serde(rename = "l")]
+    pub light: usize,
+    #[serde(rename = "d")]
+    pub dark: usize,
+}
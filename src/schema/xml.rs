@@ -13,13 +13,13 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Background {
     pub starttime: StartTime,
     pub images: Vec<Image>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct StartTime {
     pub year: i32,
     pub month: u32,
@@ -29,18 +29,77 @@ pub struct StartTime {
     pub second: u16,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Image {
     Static {
         duration: f32,
-        file: String,
+        file: File,
         idx: usize,
     },
     Transition {
         kind: String,
         duration: f32,
-        from: String,
-        to: String,
+        from: File,
+        to: File,
         idx: usize,
     },
 }
+
+/// Image referred to by a slideshow, either a single file or alternatives in
+/// different resolutions of which GNOME picks the one best fitting the screen.
+#[derive(Debug, Clone, PartialEq)]
+pub enum File {
+    Single(String),
+    Sizes(Vec<Size>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
+    pub file: String,
+}
+
+impl File {
+    /// Path of the file, or of the largest alternative.
+    pub fn path(&self) -> &str {
+        match self {
+            File::Single(file) => file,
+            File::Sizes(sizes) => sizes
+                .iter()
+                .max_by_key(|size| size.width as u64 * size.height as u64)
+                .map(|size| size.file.as_str())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Paths of all alternatives.
+    pub fn paths(&self) -> Vec<&str> {
+        match self {
+            File::Single(file) => vec![file],
+            File::Sizes(sizes) => sizes.iter().map(|size| size.file.as_str()).collect(),
+        }
+    }
+}
+
+impl From<String> for File {
+    fn from(file: String) -> Self {
+        File::Single(file)
+    }
+}
+
+/// Entries as found in `gnome-background-properties`, used to offer separate
+/// images for the light and dark style.
+#[derive(Debug)]
+pub struct Wallpapers {
+    pub wallpapers: Vec<Wallpaper>,
+}
+
+#[derive(Debug)]
+pub struct Wallpaper {
+    pub name: String,
+    pub filename: String,
+    pub filename_dark: Option<String>,
+    pub options: String,
+    pub shade_type: String,
+}
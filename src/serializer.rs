@@ -13,12 +13,14 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
-use crate::schema::xml::{Background, Image, StartTime};
+use crate::schema::xml::{Background, File, Image, StartTime, Wallpaper, Wallpapers};
 use anyhow::Result;
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::Writer;
 use std::io::Write;
 
 pub struct GnomeXMLBackgroundSerializer<'a, T: Write> {
-    writer: &'a mut T,
+    writer: Writer<&'a mut T>,
 }
 
 impl<'a, T> GnomeXMLBackgroundSerializer<'a, T>
@@ -26,7 +28,9 @@ where
     T: Write,
 {
     pub fn new(writer: &'a mut T) -> Self {
-        Self { writer }
+        Self {
+            writer: Writer::new_with_indent(writer, b'\t', 1),
+        }
     }
 
     pub fn serialize(&mut self, background: &Background) -> Result<()> {
@@ -39,23 +43,24 @@ where
             minute,
             second,
         } = background.starttime;
-        writeln!(self.writer, "<background>")?;
-        writeln!(self.writer, "\t<starttime>")?;
-        writeln!(self.writer, "\t\t<year>{}</year>", year)?;
-        writeln!(self.writer, "\t\t<month>{}</month>", month)?;
-        writeln!(self.writer, "\t\t<day>{}</day>", day)?;
-        writeln!(self.writer, "\t\t<hour>{}</hour>", hour)?;
-        writeln!(self.writer, "\t\t<minute>{}</minute>", minute)?;
-        writeln!(self.writer, "\t\t<second>{}</second>", second)?;
-        writeln!(self.writer, "\t</starttime>")?;
+        declaration(&mut self.writer)?;
+        start(&mut self.writer, BytesStart::new("background"))?;
+        start(&mut self.writer, BytesStart::new("starttime"))?;
+        text_element(&mut self.writer, "year", &year.to_string())?;
+        text_element(&mut self.writer, "month", &month.to_string())?;
+        text_element(&mut self.writer, "day", &day.to_string())?;
+        text_element(&mut self.writer, "hour", &hour.to_string())?;
+        text_element(&mut self.writer, "minute", &minute.to_string())?;
+        text_element(&mut self.writer, "second", &second.to_string())?;
+        end(&mut self.writer, "starttime")?;
 
         for entry in background.images.iter() {
             match entry {
                 Image::Static { duration, file, .. } => {
-                    writeln!(self.writer, "\t<static>")?;
-                    writeln!(self.writer, "\t\t<duration>{}</duration>", duration)?;
-                    writeln!(self.writer, "\t\t<file>{}</file>", file)?;
-                    writeln!(self.writer, "\t</static>")?;
+                    start(&mut self.writer, BytesStart::new("static"))?;
+                    text_element(&mut self.writer, "duration", &duration.to_string())?;
+                    self.write_file("file", file)?;
+                    end(&mut self.writer, "static")?;
                 }
                 Image::Transition {
                     kind,
@@ -64,16 +69,182 @@ where
                     to,
                     ..
                 } => {
-                    writeln!(self.writer, "\t<transition type=\"{}\">", kind)?;
-                    writeln!(self.writer, "\t\t<duration>{}</duration>", duration)?;
-                    writeln!(self.writer, "\t\t<from>{}</from>", from)?;
-                    writeln!(self.writer, "\t\t<to>{}</to>", to)?;
-                    writeln!(self.writer, "\t</transition>")?;
+                    let mut transition = BytesStart::new("transition");
+                    transition.push_attribute(("type", kind.as_str()));
+                    start(&mut self.writer, transition)?;
+                    text_element(&mut self.writer, "duration", &duration.to_string())?;
+                    self.write_file("from", from)?;
+                    self.write_file("to", to)?;
+                    end(&mut self.writer, "transition")?;
+                }
+            }
+        }
+        end(&mut self.writer, "background")?;
+        self.writer.get_mut().flush()?;
+        Ok(())
+    }
+
+    fn write_file(&mut self, element: &str, file: &File) -> Result<()> {
+        match file {
+            File::Single(file) => text_element(&mut self.writer, element, file)?,
+            File::Sizes(sizes) => {
+                start(&mut self.writer, BytesStart::new(element))?;
+                for size in sizes.iter() {
+                    self.writer
+                        .create_element("size")
+                        .with_attribute(("width", size.width.to_string().as_str()))
+                        .with_attribute(("height", size.height.to_string().as_str()))
+                        .write_text_content(BytesText::new(&size.file))?;
                 }
+                end(&mut self.writer, element)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct GnomeXMLPropertiesSerializer<'a, T: Write> {
+    writer: Writer<&'a mut T>,
+}
+
+impl<'a, T> GnomeXMLPropertiesSerializer<'a, T>
+where
+    T: Write,
+{
+    pub fn new(writer: &'a mut T) -> Self {
+        Self {
+            writer: Writer::new_with_indent(writer, b'\t', 1),
+        }
+    }
+
+    pub fn serialize(&mut self, properties: &Wallpapers) -> Result<()> {
+        declaration(&mut self.writer)?;
+        self.writer.write_event(Event::DocType(BytesText::from_escaped(
+            r#"wallpapers SYSTEM "gnome-wp-list.dtd""#,
+        )))?;
+        start(&mut self.writer, BytesStart::new("wallpapers"))?;
+        for Wallpaper {
+            name,
+            filename,
+            filename_dark,
+            options,
+            shade_type,
+        } in properties.wallpapers.iter()
+        {
+            let mut wallpaper = BytesStart::new("wallpaper");
+            wallpaper.push_attribute(("deleted", "false"));
+            start(&mut self.writer, wallpaper)?;
+            text_element(&mut self.writer, "name", name)?;
+            text_element(&mut self.writer, "filename", filename)?;
+            if let Some(filename_dark) = filename_dark {
+                text_element(&mut self.writer, "filename-dark", filename_dark)?;
             }
+            text_element(&mut self.writer, "options", options)?;
+            text_element(&mut self.writer, "shade_type", shade_type)?;
+            end(&mut self.writer, "wallpaper")?;
         }
-        write!(self.writer, "</background>")?;
-        self.writer.flush()?;
+        end(&mut self.writer, "wallpapers")?;
+        self.writer.get_mut().flush()?;
         Ok(())
     }
 }
+
+fn declaration<W: Write>(writer: &mut Writer<W>) -> Result<()> {
+    writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))?;
+    Ok(())
+}
+
+fn start<W: Write>(writer: &mut Writer<W>, element: BytesStart) -> Result<()> {
+    writer.write_event(Event::Start(element))?;
+    Ok(())
+}
+
+fn end<W: Write>(writer: &mut Writer<W>, name: &str) -> Result<()> {
+    writer.write_event(Event::End(BytesEnd::new(name)))?;
+    Ok(())
+}
+
+/// Element only containing the given text, which is escaped as needed.
+fn text_element<W: Write>(writer: &mut Writer<W>, name: &str, text: &str) -> Result<()> {
+    writer
+        .create_element(name)
+        .write_text_content(BytesText::new(text))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{GnomeXMLBackgroundParser, GnomeXMLPropertiesParser};
+
+    const PATH: &str = r#"/a&b/<c>"d'.png"#;
+    const ESCAPED: &str = "/a&amp;b/&lt;c&gt;&quot;d&apos;.png";
+
+    #[test]
+    fn escaped_background() {
+        let file = File::Single(PATH.to_string());
+        let background = Background {
+            starttime: StartTime {
+                year: 2024,
+                month: 6,
+                day: 21,
+                hour: 0,
+                minute: 0,
+                second: 0,
+            },
+            images: vec![
+                Image::Static {
+                    duration: 1.0,
+                    file: file.clone(),
+                    idx: 0,
+                },
+                Image::Transition {
+                    kind: r#"over"lay"#.to_string(),
+                    duration: 86399.0,
+                    from: file.clone(),
+                    to: file,
+                    idx: 0,
+                },
+            ],
+        };
+        let mut xml = Vec::new();
+        GnomeXMLBackgroundSerializer::new(&mut xml)
+            .serialize(&background)
+            .unwrap();
+        let text = String::from_utf8(xml.clone()).unwrap();
+        assert!(text.contains(&format!("<file>{}</file>", ESCAPED)));
+        assert!(text.contains(&format!("<from>{}</from>", ESCAPED)));
+        assert!(text.contains(r#"<transition type="over&quot;lay">"#));
+        let parsed = GnomeXMLBackgroundParser::new(xml.as_slice())
+            .parse()
+            .unwrap();
+        assert_eq!(parsed, background);
+    }
+
+    #[test]
+    fn escaped_properties() {
+        let properties = Wallpapers {
+            wallpapers: vec![Wallpaper {
+                name: "Rock & Roll".to_string(),
+                filename: PATH.to_string(),
+                filename_dark: Some(PATH.to_string()),
+                options: "zoom".to_string(),
+                shade_type: "solid".to_string(),
+            }],
+        };
+        let mut xml = Vec::new();
+        GnomeXMLPropertiesSerializer::new(&mut xml)
+            .serialize(&properties)
+            .unwrap();
+        let text = String::from_utf8(xml.clone()).unwrap();
+        assert!(text.contains("<name>Rock &amp; Roll</name>"));
+        assert!(text.contains(&format!("<filename>{}</filename>", ESCAPED)));
+        let parsed = GnomeXMLPropertiesParser::new(xml.as_slice())
+            .parse()
+            .unwrap();
+        let wallpaper = &parsed.wallpapers[0];
+        assert_eq!(wallpaper.name, "Rock & Roll");
+        assert_eq!(wallpaper.filename, PATH);
+        assert_eq!(wallpaper.filename_dark.as_deref(), Some(PATH));
+    }
+}
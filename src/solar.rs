@@ -13,89 +13,299 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
-use crate::image::{self, ImagePoint};
-use crate::schema::xml::{Background, StartTime};
+use crate::adjust::Adjustment;
+use crate::error::Error;
+use crate::image::{self, Slot};
+use crate::progress::{Progress, Stage};
+use crate::schema::index::{SeasonEntry, SeasonIndex};
+use crate::schema::plist::{SolarSlice, WallpaperMetaSun};
+use crate::schema::xml::File;
+use crate::util::sun::{Location, SunDay};
 use crate::util::time;
-use crate::DAY_SECS;
-use crate::{image::process_img, metadata};
+use crate::{Options, DAY_SECS};
 use anyhow::Result;
-use chrono::Datelike;
-use indicatif::{ProgressBar, ProgressIterator, ProgressStyle};
-use libheif_rs::HeifContext;
+use chrono::{Datelike, Duration, NaiveDate};
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::io::BufWriter;
 use std::path::Path;
 
-use colored::*;
-#[derive(Debug)]
-struct SolarToHourSlice {
-    time: f32,
-    index: usize,
+// Frames which end up at the same time of day (e.g. both at noon) are kept
+// apart by at least a minute, otherwise GNOME would get zero-length transitions.
+const MIN_FRAME_DISTANCE: f32 = 60.0 / DAY_SECS;
+
+/// Length of the periods for which separate schedules are created.
+#[derive(Debug, Clone, Copy)]
+pub enum Period {
+    Month,
+    Week,
 }
 
-pub fn compute_solar_based_wallpaper(
-    image_ctx: HeifContext,
-    content: String,
+impl Period {
+    fn name(&self) -> &'static str {
+        match self {
+            Period::Month => "month",
+            Period::Week => "week",
+        }
+    }
+}
+
+/// Slots at the times the sun reaches the position of each slice on the day
+/// given in the options.
+pub fn plan(
+    plist: &WallpaperMetaSun,
+    options: &Options,
+    progress: &dyn Progress,
+) -> Result<Vec<Slot>> {
+    match options.location {
+        Some(location) => progress.message(
+            Stage::Preparation,
+            &format!(
+                "Computing sun positions for {:.4}, {:.4} on {}...",
+                location.latitude, location.longitude, options.date,
+            ),
+        ),
+        None => progress.message(
+            Stage::Preparation,
+            "No location given, approximating times from the sun's azimuth. Use --latitude and --longitude for accurate timings.",
+        ),
+    }
+    schedule_slices(
+        &plist.solar_slices,
+        options.location.as_ref(),
+        options.date,
+        &options.adjustment,
+        progress,
+    )
+}
+
+/// Create one schedule per period of the year, all referring to the same set
+/// of images, and an index listing which schedule applies to which dates.
+/// Nothing is done unless both a period and a location are given.
+pub fn export_seasons(
+    plist: &WallpaperMetaSun,
+    files: &BTreeMap<usize, File>,
     parent_directory: &Path,
     image_name: &str,
+    options: &Options,
+    progress: &dyn Progress,
 ) -> Result<()> {
-    let mut plist = metadata::get_solar_plist_from_base64(&content)?;
+    let (period, location) = match (options.period, options.location) {
+        (Some(period), Some(location)) => (period, location),
+        _ => return Ok(()),
+    };
+    let year = options.date.year();
+    progress.message(
+        Stage::Conversion,
+        &format!(
+            "Creating a schedule for every {} of {}...",
+            period.name(),
+            year
+        ),
+    );
+    let mut index = SeasonIndex {
+        name: image_name.to_string(),
+        seasons: Vec::new(),
+    };
+    for (number, (from, to)) in periods(year, period).into_iter().enumerate() {
+        // Timings are computed for the middle of the period to keep the error
+        // at its start and end as low as possible.
+        let date = from + (to - from) / 2;
+        let slots = schedule_slices(
+            &plist.solar_slices,
+            Some(&location),
+            date,
+            &options.adjustment,
+            progress,
+        )?;
+        let name = format!("{}-{}-{:02}", image_name, period.name(), number + 1);
+        let mut background = image::plan_background(&slots, files, from);
+        let file = image::save_xml(&mut background, parent_directory, &name, progress)?;
+        let dark_frames = image::frames(slots.iter().filter(|slot| slot.dark), files);
+        let file_dark =
+            image::save_dark_variant(&dark_frames, from, parent_directory, &name, progress)?;
+        index.seasons.push(SeasonEntry {
+            from,
+            to,
+            file,
+            file_dark,
+        });
+    }
+
+    progress.message(Stage::Conversion, "Writing schedule index...");
+    let index_path = parent_directory.join(format!("{}-seasons.json", image_name));
+    let index_file = std::fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(&index_path)
+        .map_err(|source| Error::Io {
+            path: index_path.clone(),
+            source,
+        })?;
+    serde_json::to_writer_pretty(BufWriter::new(index_file), &index)?;
+    Ok(())
+}
+
+/// First and last day of each period in the given year. Weeks are counted
+/// from the first of January, the last week of a year is shortened.
+fn periods(year: i32, period: Period) -> Vec<(NaiveDate, NaiveDate)> {
+    let first = NaiveDate::from_ymd_opt(year, 1, 1).expect("January first is always valid");
+    let last = NaiveDate::from_ymd_opt(year, 12, 31).expect("December 31st is always valid");
+    match period {
+        Period::Month => (1..=12)
+            .map(|month| {
+                let from = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+                let to = NaiveDate::from_ymd_opt(year, month + 1, 1)
+                    .map(|next| next - Duration::days(1))
+                    .unwrap_or(last);
+                (from, to)
+            })
+            .collect(),
+        Period::Week => first
+            .iter_days()
+            .step_by(7)
+            .take_while(|from| *from <= last)
+            .map(|from| (from, (from + Duration::days(6)).min(last)))
+            .collect(),
+    }
+}
+
+/// Times of all slices sorted by their time of day, after the adjustment is
+/// applied.
+fn schedule_slices(
+    slices: &[SolarSlice],
+    location: Option<&Location>,
+    date: NaiveDate,
+    adjustment: &Adjustment,
+    progress: &dyn Progress,
+) -> Result<Vec<Slot>> {
+    let mut time_slices = location
+        .and_then(|location| time_slices_from_location(slices, location, date, progress))
+        .unwrap_or_else(|| time_slices_from_azimuth(slices));
+    time_slices.sort_by(|x, y| x.time.partial_cmp(&y.time).unwrap_or(Ordering::Equal));
+    for idx in 1..time_slices.len() {
+        let earliest = time_slices[idx - 1].time + MIN_FRAME_DISTANCE;
+        if time_slices[idx].time < earliest {
+            time_slices[idx].time = earliest;
+        }
+    }
+    // Frames pushed past midnight are shown at the start of the day instead
+    for slot in time_slices.iter_mut() {
+        slot.time = time::wrap_time_of_day(slot.time);
+    }
+    time_slices.sort_by(|x, y| x.time.partial_cmp(&y.time).unwrap_or(Ordering::Equal));
+    adjustment.apply(&mut time_slices)?;
+    Ok(time_slices)
+}
 
-    plist
-        .solar_slices
-        .sort_by(|x, y| x.azimuth.partial_cmp(&y.azimuth).unwrap_or(Ordering::Equal));
-    let time_slices: Vec<SolarToHourSlice> = plist
-        .solar_slices
+/// Rough estimation used when no location is known, the azimuth is treated as
+/// if the sun would move with constant speed around the observer.
+fn time_slices_from_azimuth(slices: &[SolarSlice]) -> Vec<Slot> {
+    slices
         .iter()
-        .map(|elem| SolarToHourSlice {
+        .map(|elem| Slot {
             time: elem.azimuth / 360f32,
-            index: elem.idx,
+            image: elem.idx,
+            dark: elem.light_mode == Some(0),
         })
-        .collect();
-    let mut img_ids = vec![0; image_ctx.number_of_top_level_images()];
-    image_ctx.top_level_image_ids(&mut img_ids);
-
-    let start_time = time_slices.get(0).expect("No image has been found").time;
-    let start_seconds = (start_time * DAY_SECS) as u16;
-    let date = chrono::Local::now();
-    let mut background_definition = Background {
-        starttime: StartTime {
-            year: date.year(),
-            month: date.month(),
-            day: date.day(),
-            hour: time::to_rem_hours(start_seconds),
-            minute: time::to_rem_min(start_seconds),
-            second: time::to_rem_sec(start_seconds),
-        },
-        images: vec![],
+        .collect()
+}
+
+/// Determine at which local time the sun reaches the altitude of each slice.
+/// Slices east of the meridian are placed in the morning, all others in the
+/// evening. Returns `None` if the sun does not rise or set on that day.
+fn time_slices_from_location(
+    slices: &[SolarSlice],
+    location: &Location,
+    date: NaiveDate,
+    progress: &dyn Progress,
+) -> Option<Vec<Slot>> {
+    let day = SunDay::new(location, date);
+    if day.max_altitude() <= 0.0 || day.min_altitude() >= 0.0 {
+        progress.warning(&format!(
+            "The sun does not rise and set on {} at this location.",
+            date
+        ));
+        return None;
+    }
+
+    // The slices describe the sun's course at the location the wallpaper has
+    // been made for. Their altitudes are scaled to the range of this location,
+    // so that the highest slice is shown at noon and the lowest at midnight,
+    // keeping sunrise and sunset on the horizon.
+    let highest = slices.iter().map(|s| s.altitude as f64).fold(0.0, f64::max);
+    let lowest = slices.iter().map(|s| s.altitude as f64).fold(0.0, f64::min);
+    let above = if highest > 0.0 {
+        day.max_altitude() / highest
+    } else {
+        1.0
+    };
+    let below = if lowest < 0.0 {
+        day.min_altitude() / lowest
+    } else {
+        1.0
     };
 
-    println!(
-        "{}: Converting embedded images to png format...",
-        "Conversion".green(),
-    );
-    println!("{}:", "Conversion".green());
-    let pb = ProgressBar::new(image_ctx.number_of_top_level_images() as u64).with_style(
-        ProgressStyle::default_bar()
-            .template("[{wide_bar}] {pos}/{len} [ETA: {eta_precise}]").unwrap()
-            .progress_chars("## "),
-    );
-    for (idx, SolarToHourSlice { time, index }) in time_slices.iter().enumerate().progress_with(pb)
-    {
-        let img_id = img_ids[*index];
-        let pt = ImagePoint {
-            image_ctx: &image_ctx,
-            img_id,
-            index: idx,
-            background: &mut background_definition,
-            parent_directory,
-            start_time,
-            time: *time,
-            next_time: time_slices
-                .get(idx + 1)
-                .map(|elem| elem.time)
-                .unwrap_or(0f32),
+    Some(
+        slices
+            .iter()
+            .map(|elem| {
+                let altitude = elem.altitude as f64;
+                let altitude = if altitude > 0.0 {
+                    altitude * above
+                } else {
+                    altitude * below
+                };
+                Slot {
+                    time: day.time_of_altitude(altitude, elem.azimuth < 180.0),
+                    image: elem.idx,
+                    dark: elem.light_mode == Some(0),
+                }
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::Silent;
+
+    fn slice(idx: usize, azimuth: f32) -> SolarSlice {
+        SolarSlice {
+            altitude: 0.0,
+            idx,
+            light_mode: None,
+            azimuth,
+        }
+    }
+
+    #[test]
+    fn highest_frame_at_noon() {
+        let tokyo = Location {
+            latitude: 35.7,
+            longitude: 139.7,
         };
-        process_img(pt)?;
+        let date = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        let mut slices = [slice(0, 70.0), slice(1, 180.0), slice(2, 290.0)];
+        slices[0].altitude = -5.0;
+        slices[1].altitude = 50.0;
+        slices[2].altitude = 20.0;
+        let slots = time_slices_from_location(&slices, &tokyo, date, &Silent).unwrap();
+        let day = SunDay::new(&tokyo, date);
+        let noon = day.time_of_altitude(day.max_altitude(), true);
+        let peak = slots.iter().find(|slot| slot.image == 1).unwrap();
+        assert!((peak.time - noon).abs() * 24.0 * 60.0 <= 1.0);
+    }
+
+    #[test]
+    fn frames_pushed_past_midnight_wrap() {
+        let slices = [slice(0, 359.99), slice(1, 359.99), slice(2, 359.99)];
+        let date = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        let slots =
+            schedule_slices(&slices, None, date, &Adjustment::default(), &Silent).unwrap();
+        assert!(slots.iter().all(|slot| (0.0..1.0).contains(&slot.time)));
+        assert!(slots.windows(2).all(|pair| pair[0].time <= pair[1].time));
     }
-    image::save_xml(&mut background_definition, parent_directory, image_name)
 }
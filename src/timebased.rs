@@ -13,115 +13,25 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
-use std::path::Path;
-
-use crate::image::{process_img, save_xml, ImagePoint};
-use crate::metadata;
-use crate::schema::plist::TimeSlice;
-use crate::schema::xml::{Background, StartTime};
-use crate::DAY_SECS;
-
-use crate::util::time;
+use crate::image::Slot;
+use crate::schema::plist::{TimeSlice, WallpaperMetaTime};
+use crate::Options;
 use anyhow::Result;
-use chrono::Datelike;
-use colored::*;
-use indicatif::{ProgressBar, ProgressIterator, ProgressStyle};
-use libheif_rs::HeifContext;
-
-pub fn compute_time_based_wallpaper(
-    image_ctx: HeifContext,
-    content: String,
-    parent_directory: &Path,
-    image_name: &str,
-) -> Result<()> {
-    let mut plist = metadata::get_time_plist_from_base64(&content)?;
-    //println!("Found plist {:?}", plist);
+use std::cmp::Ordering;
 
-    plist
+/// Slots in the order given by the time of each slice, adjusted as given in
+/// the options.
+pub fn plan(plist: &WallpaperMetaTime, options: &Options) -> Result<Vec<Slot>> {
+    let mut slots: Vec<Slot> = plist
         .time_slices
-        .sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
-    let start_time = plist.time_slices.get(0).unwrap().time;
-    let start_seconds = (start_time * DAY_SECS) as u16;
-    let date = chrono::Local::now();
-    let mut xml_background = Background {
-        images: Vec::new(),
-        starttime: StartTime {
-            year: date.year(),
-            month: date.month(),
-            day: date.day(),
-            hour: time::to_rem_hours(start_seconds),
-            minute: time::to_rem_min(start_seconds),
-            second: time::to_rem_sec(start_seconds),
-        },
-    };
-
-    let number_of_images = image_ctx.number_of_top_level_images();
-    println!(
-        "{}: Found {} images",
-        "Preparation".bright_blue(),
-        number_of_images,
-    );
-    let mut image_ids = vec![0u32; number_of_images];
-    image_ctx.top_level_image_ids(&mut image_ids);
-    println!(
-        "{}: Converting embedded images to png format",
-        "Conversion".green(),
-    );
-    println!("{}:", "Conversion".green());
-    let pb = ProgressBar::new(number_of_images as u64).with_style(
-        ProgressStyle::default_bar()
-            .template("[{wide_bar}] {pos}/{len} [ETA: {eta_precise}]").unwrap()
-            .progress_chars("## "),
-    );
-    for (time_idx, TimeSlice { time, idx }) in
-        plist.time_slices.iter().enumerate().progress_with(pb)
-    {
-        let img_id = *image_ids
-            .get(*idx)
-            .expect("Could not fetch image id described in metadata");
-        //println!("Image ID: {:?}", img_id);
-        let pt = ImagePoint {
-            image_ctx: &image_ctx,
-            img_id,
-            index: time_idx,
-            background: &mut xml_background,
-            parent_directory,
-            start_time,
-            time: *time,
-            next_time: plist
-                .time_slices
-                .get(time_idx + 1)
-                .map(|elem| elem.time)
-                .unwrap_or(0f32),
-        };
-        process_img(pt)?;
-    }
-
-    // Valify time range
-    let total_time = xml_background
-        .images
         .iter()
-        .fold(0f32, |acc, image| match image {
-            crate::schema::xml::Image::Static { duration, .. } => acc + duration,
-            crate::schema::xml::Image::Transition { duration, .. } => acc + duration,
-        });
-
-    if total_time < DAY_SECS as f32 {
-        if let Some(img) = xml_background.images.last_mut() {
-            match img {
-                crate::schema::xml::Image::Static {
-                    ref mut duration, ..
-                } => {
-                    *duration = (*duration + (DAY_SECS as f32 - total_time)).ceil();
-                }
-                crate::schema::xml::Image::Transition {
-                    ref mut duration, ..
-                } => {
-                    *duration = (*duration + (DAY_SECS as f32 - total_time)).ceil();
-                }
-            }
-        }
-    }
-
-    save_xml(&mut xml_background, parent_directory, image_name)
+        .map(|TimeSlice { time, idx }| Slot {
+            time: *time,
+            image: *idx,
+            dark: false,
+        })
+        .collect();
+    slots.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(Ordering::Equal));
+    options.adjustment.apply(&mut slots)?;
+    Ok(slots)
 }
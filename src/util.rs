@@ -13,5 +13,11 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
+pub mod color;
+pub mod decode;
+pub mod encode;
+pub mod path;
 pub mod png;
+pub mod resize;
+pub mod sun;
 pub mod time;
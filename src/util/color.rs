@@ -0,0 +1,103 @@
+// heic-to-dynamic-gnome-wallpaper
+// Copyright (C) 2022 Johannes Wünsche
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::error::Error;
+use anyhow::Result;
+use image::DynamicImage;
+use libheif_rs::ImageHandle;
+use moxcms::{
+    CicpColorPrimaries, CicpProfile, ColorProfile, Layout, MatrixCoefficients,
+    TransferCharacteristics, TransformOptions,
+};
+use std::convert::TryFrom;
+
+/// ICC profile of an image of the container. Profiles given as nclx
+/// parameters, as done by many Apple wallpapers, are converted to ICC.
+pub fn icc_profile(handle: &ImageHandle) -> Result<Option<Vec<u8>>> {
+    if let Some(profile) = handle.color_profile_raw() {
+        return Ok(Some(profile.data));
+    }
+    let nclx = match handle.color_profile_nclx() {
+        Some(nclx) => nclx,
+        None => return Ok(None),
+    };
+    let cicp = CicpProfile {
+        color_primaries: CicpColorPrimaries::try_from(nclx.color_primaries() as u8)
+            .map_err(cms_error)?,
+        transfer_characteristics: TransferCharacteristics::try_from(
+            nclx.transfer_characteristics() as u8,
+        )
+        .map_err(cms_error)?,
+        matrix_coefficients: MatrixCoefficients::try_from(nclx.matrix_coefficients() as u8)
+            .map_err(cms_error)?,
+        full_range: nclx.full_range_flag() != 0,
+    };
+    let profile = ColorProfile::new_from_cicp(cicp);
+    Ok(Some(profile.encode().map_err(cms_error)?))
+}
+
+/// Convert the colors of an image in the given ICC profile to sRGB. The alpha
+/// channel is kept as it is.
+pub fn convert_to_srgb(image: DynamicImage, icc: &[u8]) -> Result<DynamicImage> {
+    let source = ColorProfile::new_from_slice(icc).map_err(cms_error)?;
+    let srgb = ColorProfile::new_srgb();
+    let options = TransformOptions::default();
+    let transform_16bit = |layout| {
+        source
+            .create_transform_16bit(layout, &srgb, layout, options)
+            .map_err(cms_error)
+    };
+    let transform_8bit = |layout| {
+        source
+            .create_transform_8bit(layout, &srgb, layout, options)
+            .map_err(cms_error)
+    };
+    match image {
+        DynamicImage::ImageRgb16(mut image) => {
+            let source = image.as_raw().clone();
+            transform_16bit(Layout::Rgb)?
+                .transform(&source, &mut image)
+                .map_err(cms_error)?;
+            Ok(DynamicImage::ImageRgb16(image))
+        }
+        DynamicImage::ImageRgba16(mut image) => {
+            let source = image.as_raw().clone();
+            transform_16bit(Layout::Rgba)?
+                .transform(&source, &mut image)
+                .map_err(cms_error)?;
+            Ok(DynamicImage::ImageRgba16(image))
+        }
+        image if image.color().has_alpha() => {
+            let mut image = image.into_rgba8();
+            let source = image.as_raw().clone();
+            transform_8bit(Layout::Rgba)?
+                .transform(&source, &mut image)
+                .map_err(cms_error)?;
+            Ok(DynamicImage::ImageRgba8(image))
+        }
+        image => {
+            let mut image = image.into_rgb8();
+            let source = image.as_raw().clone();
+            transform_8bit(Layout::Rgb)?
+                .transform(&source, &mut image)
+                .map_err(cms_error)?;
+            Ok(DynamicImage::ImageRgb8(image))
+        }
+    }
+}
+
+fn cms_error(error: moxcms::CmsError) -> Error {
+    Error::InvalidProfile(error.to_string())
+}
@@ -0,0 +1,111 @@
+// heic-to-dynamic-gnome-wallpaper
+// Copyright (C) 2022 Johannes Wünsche
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use super::encode::Depth;
+use crate::error::Error;
+use image::{DynamicImage, ImageBuffer};
+use libheif_rs::{ColorSpace, ImageHandle, LibHeif, Plane, RgbChroma};
+
+/// Decode an image of the container with the given bit depth per channel.
+/// Images with alpha channel are decoded to RGBA, all others to RGB, which
+/// includes monochrome images as libheif converts them while decoding. Sources
+/// with fewer bits than requested are scaled up to the full range.
+pub fn decode(handle: &ImageHandle, depth: Depth) -> Result<DynamicImage, Error> {
+    let alpha = handle.has_alpha_channel();
+    let high = depth == Depth::Sixteen && handle.luma_bits_per_pixel() > 8;
+    let chroma = match (high, alpha) {
+        (false, false) => RgbChroma::Rgb,
+        (false, true) => RgbChroma::Rgba,
+        (true, false) => RgbChroma::HdrRgbBe,
+        (true, true) => RgbChroma::HdrRgbaBe,
+    };
+    let decoded = LibHeif::new()
+        .decode(handle, ColorSpace::Rgb(chroma), None)
+        .map_err(|e| Error::DecodeFailed(format!("RGB colorspace not available: {:?}", e)))?;
+    let planes = decoded.planes();
+    let plane = planes
+        .interleaved
+        .ok_or_else(|| Error::DecodeFailed("no interleaved plane".to_string()))?;
+    let channels = if alpha { 4 } else { 3 };
+
+    let image = if high {
+        // Values only cover the range of the source's bit depth, e.g. 0 to
+        // 1023 for 10 bit images
+        let bits = match plane.bits_per_pixel {
+            0 => handle.luma_bits_per_pixel(),
+            bits => bits,
+        }
+        .clamp(1, 16);
+        let max = (1u32 << bits) - 1;
+        let pixels = rows(&plane, channels * 2)?
+            .chunks_exact(2)
+            .map(|value| {
+                let value = u16::from_be_bytes([value[0], value[1]]) as u32;
+                (value.min(max) * u16::MAX as u32 / max) as u16
+            })
+            .collect();
+        if alpha {
+            DynamicImage::ImageRgba16(buffer(&plane, pixels)?)
+        } else {
+            DynamicImage::ImageRgb16(buffer(&plane, pixels)?)
+        }
+    } else {
+        let pixels = rows(&plane, channels)?;
+        if alpha {
+            DynamicImage::ImageRgba8(buffer(&plane, pixels)?)
+        } else {
+            DynamicImage::ImageRgb8(buffer(&plane, pixels)?)
+        }
+    };
+
+    Ok(match (depth, image) {
+        (Depth::Sixteen, DynamicImage::ImageRgb8(image)) => {
+            DynamicImage::ImageRgb16(DynamicImage::ImageRgb8(image).to_rgb16())
+        }
+        (Depth::Sixteen, DynamicImage::ImageRgba8(image)) => {
+            DynamicImage::ImageRgba16(DynamicImage::ImageRgba8(image).to_rgba16())
+        }
+        (_, image) => image,
+    })
+}
+
+/// Data of all rows of an interleaved plane without the padding at their end,
+/// `bytes_per_pixel` covering all channels of a pixel.
+fn rows(plane: &Plane<&[u8]>, bytes_per_pixel: usize) -> Result<Vec<u8>, Error> {
+    let row = plane.width as usize * bytes_per_pixel;
+    if plane.stride < row {
+        return Err(Error::DecodeFailed(format!(
+            "rows are {} bytes long, expected at least {}",
+            plane.stride, row
+        )));
+    }
+    let mut data = Vec::with_capacity(row * plane.height as usize);
+    for y in 0..plane.height as usize {
+        let start = y * plane.stride;
+        data.extend_from_slice(plane.data.get(start..start + row).ok_or_else(incomplete)?);
+    }
+    Ok(data)
+}
+
+fn buffer<P: image::Pixel>(
+    plane: &Plane<&[u8]>,
+    pixels: Vec<P::Subpixel>,
+) -> Result<ImageBuffer<P, Vec<P::Subpixel>>, Error> {
+    ImageBuffer::from_raw(plane.width, plane.height, pixels).ok_or_else(incomplete)
+}
+
+fn incomplete() -> Error {
+    Error::DecodeFailed("the data is incomplete".to_string())
+}
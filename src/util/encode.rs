@@ -0,0 +1,253 @@
+// heic-to-dynamic-gnome-wallpaper
+// Copyright (C) 2022 Johannes Wünsche
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use super::png::write_png;
+use crate::error::Error;
+use anyhow::Result;
+use image::codecs::jpeg::JpegEncoder;
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// File format extracted images are written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Png,
+    Jpeg,
+    Webp,
+    Avif,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Jpeg => "jpg",
+            Format::Webp => "webp",
+            Format::Avif => "avif",
+        }
+    }
+
+    /// Whether color profiles can be embedded into images of the format.
+    pub fn embeds_icc(&self) -> bool {
+        matches!(self, Format::Png | Format::Jpeg)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Jpeg => "jpeg",
+            Format::Webp => "webp",
+            Format::Avif => "avif",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(format: &str) -> Result<Self> {
+        match format {
+            "png" => Ok(Format::Png),
+            "jpeg" | "jpg" => Ok(Format::Jpeg),
+            "webp" => Ok(Format::Webp),
+            "avif" => Ok(Format::Avif),
+            _ => Err(Error::InvalidArgument(format!(
+                "Unknown image format \"{}\", expected png, jpeg, webp or avif",
+                format
+            ))
+            .into()),
+        }
+    }
+}
+
+/// Bits per color channel of extracted images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Depth {
+    Eight,
+    /// Keeps the precision of images stored with 10 or 12 bits, only
+    /// supported by png.
+    Sixteen,
+}
+
+/// Format and quality extracted images are written with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Encoding {
+    pub format: Format,
+    pub depth: Depth,
+    /// Quality from 0 to 100 for the lossy formats. For png, which is always
+    /// lossless, it selects how hard to compress: up to 33 is fast, from 67
+    /// on the best compression is used. WebP at 100 is lossless.
+    pub quality: Option<u8>,
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Self {
+            format: Format::Png,
+            depth: Depth::Eight,
+            quality: None,
+        }
+    }
+}
+
+impl Encoding {
+    /// Fails for combinations the format cannot store and qualities out of
+    /// range.
+    pub fn check(&self) -> Result<()> {
+        if let Some(quality) = self.quality.filter(|quality| *quality > 100) {
+            return Err(Error::InvalidArgument(format!(
+                "Invalid quality {}, expected a value from 0 to 100",
+                quality
+            ))
+            .into());
+        }
+        if self.depth == Depth::Sixteen && self.format != Format::Png {
+            return Err(Error::InvalidArgument(format!(
+                "Images with 16 bits per channel can only be written as png, not as {}",
+                self.format.name()
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    fn quality(&self) -> u8 {
+        self.quality.unwrap_or(match self.format {
+            Format::Png => 50,
+            Format::Jpeg | Format::Webp => 90,
+            Format::Avif => 80,
+        })
+    }
+}
+
+/// Write the image to `path` in the given encoding. The ICC profile the
+/// colors are given in is embedded into png and jpeg images, the other
+/// formats are written without it. Jpeg drops the alpha channel. The encoding
+/// has to be validated with [`Encoding::check`] before.
+pub fn write_image(
+    path: &str,
+    image: &DynamicImage,
+    icc: Option<&[u8]>,
+    encoding: &Encoding,
+) -> Result<()> {
+    let quality = encoding.quality();
+    let (width, height) = (image.width(), image.height());
+    match encoding.format {
+        Format::Png => {
+            let compression = match quality {
+                0..=33 => png::Compression::Fast,
+                34..=66 => png::Compression::Default,
+                _ => png::Compression::Best,
+            };
+            return Ok(write_png(path, image, icc, compression)?);
+        }
+        Format::Jpeg => {
+            let mut jpeg = Vec::new();
+            JpegEncoder::new_with_quality(&mut jpeg, quality)
+                .encode_image(&image.to_rgb8())
+                .map_err(|e| Error::EncodeFailed(e.to_string()))?;
+            if let Some(icc) = icc {
+                jpeg = with_icc_segments(jpeg, icc)?;
+            }
+            write(path, &jpeg)?;
+        }
+        Format::Webp => {
+            let alpha = image.color().has_alpha();
+            let pixels = match alpha {
+                true => image.to_rgba8().into_raw(),
+                false => image.to_rgb8().into_raw(),
+            };
+            let encoder = match alpha {
+                true => webp::Encoder::from_rgba(&pixels, width, height),
+                false => webp::Encoder::from_rgb(&pixels, width, height),
+            };
+            let encoded = if quality == 100 {
+                encoder.encode_lossless()
+            } else {
+                encoder.encode(quality as f32)
+            };
+            write(path, &encoded)?;
+        }
+        Format::Avif => {
+            let encoder = ravif::Encoder::new().with_quality(quality as f32);
+            let encoded = if image.color().has_alpha() {
+                let pixels: Vec<ravif::RGBA8> = image
+                    .to_rgba8()
+                    .pixels()
+                    .map(|pixel| ravif::RGBA8::new(pixel[0], pixel[1], pixel[2], pixel[3]))
+                    .collect();
+                encoder.encode_rgba(ravif::Img::new(&pixels, width as usize, height as usize))
+            } else {
+                let pixels: Vec<ravif::RGB8> = image
+                    .to_rgb8()
+                    .pixels()
+                    .map(|pixel| ravif::RGB8::new(pixel[0], pixel[1], pixel[2]))
+                    .collect();
+                encoder.encode_rgb(ravif::Img::new(&pixels, width as usize, height as usize))
+            }
+            .map_err(|e| Error::EncodeFailed(format!("{} as {}", e, encoding.format.name())))?;
+            write(path, &encoded.avif_file)?;
+        }
+    }
+    Ok(())
+}
+
+fn write(path: &str, data: &[u8]) -> Result<(), Error> {
+    std::fs::write(path, data).map_err(|source| Error::Io {
+        path: path.into(),
+        source,
+    })
+}
+
+/// Insert the ICC profile as `APP2` `ICC_PROFILE` segments into a jpeg, after
+/// the `APP0` segment if there is one. Large profiles are split across
+/// several numbered segments.
+fn with_icc_segments(jpeg: Vec<u8>, icc: &[u8]) -> Result<Vec<u8>, Error> {
+    const SIGNATURE: &[u8] = b"ICC_PROFILE\0";
+    const MAX_CHUNK: usize = u16::MAX as usize - 2 - SIGNATURE.len() - 2;
+
+    if !jpeg.starts_with(&[0xFF, 0xD8]) {
+        return Err(Error::EncodeFailed(
+            "the jpeg has no start of image marker".to_string(),
+        ));
+    }
+    let mut position = 2;
+    if jpeg.get(2..4) == Some(&[0xFF, 0xE0]) {
+        let length = u16::from_be_bytes([jpeg[4], jpeg[5]]) as usize;
+        position += 2 + length;
+    }
+    let chunks: Vec<&[u8]> = icc.chunks(MAX_CHUNK).collect();
+    if chunks.len() > u8::MAX as usize {
+        return Err(Error::EncodeFailed(
+            "the color profile is too large for a jpeg".to_string(),
+        ));
+    }
+
+    let mut result = Vec::with_capacity(jpeg.len() + icc.len() + chunks.len() * 18);
+    result.extend_from_slice(&jpeg[..position]);
+    for (number, chunk) in chunks.iter().enumerate() {
+        let length = (2 + SIGNATURE.len() + 2 + chunk.len()) as u16;
+        result.extend_from_slice(&[0xFF, 0xE2]);
+        result.extend_from_slice(&length.to_be_bytes());
+        result.extend_from_slice(SIGNATURE);
+        result.extend_from_slice(&[number as u8 + 1, chunks.len() as u8]);
+        result.extend_from_slice(chunk);
+    }
+    result.extend_from_slice(&jpeg[position..]);
+    Ok(result)
+}
@@ -0,0 +1,35 @@
+// heic-to-dynamic-gnome-wallpaper
+// Copyright (C) 2022 Johannes Wünsche
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::error::Error;
+use anyhow::Result;
+use std::path::Path;
+
+/// The path as string, as it has to be written into descriptions and handed
+/// to libheif. Paths which are not valid UTF-8 cannot be represented there.
+pub fn to_str(path: &Path) -> Result<&str> {
+    path.to_str().ok_or_else(|| {
+        Error::InvalidArgument(format!(
+            "The path \"{}\" is not valid UTF-8 and cannot be used",
+            path.display()
+        ))
+        .into()
+    })
+}
+
+/// Path of the file `name` in `directory`.
+pub fn file_in(directory: &Path, name: &str) -> Result<String> {
+    to_str(&directory.join(name)).map(str::to_string)
+}
@@ -13,67 +13,89 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
-use colored::*;
 use std::io::{BufWriter, Write};
 
-use anyhow::Result;
-use libheif_rs::{ColorSpace, ImageHandle, RgbChroma, LibHeif};
+use crate::error::Error;
+use flate2::write::ZlibEncoder;
+use image::DynamicImage;
 
-pub fn write_png(path: &str, handle: ImageHandle) -> Result<()> {
-    let libheif = LibHeif::new();
-    let width = handle.width();
-    let height = handle.height();
-    //let decoded = handle.decode(ColorSpace::YCbCr(libheif_rs::Chroma::C444), false).unwrap();
-    //let res = handle.decode(ColorSpace::Rgb(RgbChroma::C444), false);
-    let res = libheif.decode(&handle, ColorSpace::Rgb(RgbChroma::C444), None);
+/// Write 16 bit images with 16 bits per channel, all others with 8 bits. The
+/// ICC profile the colors are given in is embedded as `iCCP` chunk.
+pub fn write_png(
+    path: &str,
+    image: &DynamicImage,
+    icc: Option<&[u8]>,
+    compression: png::Compression,
+) -> Result<(), Error> {
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(path)
+        .map_err(|source| Error::Io {
+            path: path.into(),
+            source,
+        })?;
+    let writer = BufWriter::new(file);
 
-    if let Ok(decoded) = res {
-        let planes = decoded.planes();
-
-        let red = planes.r.unwrap().data;
-        let green = planes.g.unwrap().data;
-        let blue = planes.b.unwrap().data;
-
-        let file = std::fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(path)?;
-        let writer = BufWriter::new(file);
-
-        // In some cases data gets read which is not valid in itself, this is the case when the expected size of data (width * height) is superceded by the amount of data encoded in the planes
-        // This may have different reasons, being image blocks etc...
-        let actual = red.len() + green.len() + blue.len();
-        if red.len() != green.len() || green.len() != blue.len() {
-            return Err(anyhow::Error::msg("Length of color planes are unequal. The image data is probably invalid, please check the used image in another application."));
+    let mut pngencoder = png::Encoder::new(writer, image.width(), image.height());
+    pngencoder.set_color(if image.color().has_alpha() {
+        png::ColorType::Rgba
+    } else {
+        png::ColorType::Rgb
+    });
+    pngencoder.set_compression(compression);
+    let data = match image {
+        DynamicImage::ImageRgb16(image) => {
+            pngencoder.set_depth(png::BitDepth::Sixteen);
+            to_be_bytes(image.as_raw())
+        }
+        DynamicImage::ImageRgba16(image) => {
+            pngencoder.set_depth(png::BitDepth::Sixteen);
+            to_be_bytes(image.as_raw())
+        }
+        DynamicImage::ImageRgba8(image) => {
+            pngencoder.set_depth(png::BitDepth::Eight);
+            image.as_raw().clone()
+        }
+        image if image.color().has_alpha() => {
+            pngencoder.set_depth(png::BitDepth::Eight);
+            image.to_rgba8().into_raw()
         }
-        let expected = width * height * 3;
-        let offset = (actual as u32 - expected) / 3 / height;
+        image => {
+            pngencoder.set_depth(png::BitDepth::Eight);
+            image.to_rgb8().into_raw()
+        }
+    };
+    let mut writer = pngencoder.write_header().map_err(png_error)?;
+    write_icc(&mut writer, icc)?;
+    writer.write_image_data(&data).map_err(png_error)?;
+    writer.finish().map_err(png_error)
+}
 
-        let mut pngencoder = png::Encoder::new(writer, width, height);
-        pngencoder.set_color(png::ColorType::Rgb);
-        pngencoder.set_depth(png::BitDepth::Eight);
-        let image_writer = pngencoder.write_header()?;
-        let mut w = image_writer.into_stream_writer()?;
+/// PNG stores samples in network byte order
+fn to_be_bytes(samples: &[u16]) -> Vec<u8> {
+    samples.iter().flat_map(|value| value.to_be_bytes()).collect()
+}
 
-        //println!("Writing image");
-        for (_, ((red, green), blue)) in red
-            .iter()
-            .zip(green.iter())
-            .zip(blue.iter())
-            .enumerate()
-            .filter(|(id, _)| *id as u32 % (width + offset) < width)
-        {
-            w.write_all(&[*red, *green, *blue])?;
-        }
-        return Ok(());
+fn write_icc<W: Write>(writer: &mut png::Writer<W>, icc: Option<&[u8]>) -> Result<(), Error> {
+    if let Some(icc) = icc {
+        // Profile name, null separator and compression method 0 (zlib)
+        let mut chunk = b"ICC Profile\0\0".to_vec();
+        let mut compressor = ZlibEncoder::new(&mut chunk, flate2::Compression::default());
+        compressor
+            .write_all(icc)
+            .and_then(|_| compressor.finish())
+            .map_err(|e| {
+                Error::EncodeFailed(format!("cannot compress the color profile: {}", e))
+            })?;
+        writer
+            .write_chunk(png::chunk::iCCP, &chunk)
+            .map_err(png_error)?;
     }
-    println!(
-        "{}: Could not determine color space. Colorspace RGB C444 could not be applied",
-        "Error".red(),
-    );
-    Err(anyhow::Error::msg(format!(
-        "Could not decode the image data in RGB C444 colorspace: {:?}",
-        res.err().unwrap()
-    )))
+    Ok(())
+}
+
+fn png_error(error: png::EncodingError) -> Error {
+    Error::EncodeFailed(error.to_string())
 }
@@ -0,0 +1,139 @@
+// heic-to-dynamic-gnome-wallpaper
+// Copyright (C) 2022 Johannes Wünsche
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::error::Error;
+use anyhow::Result;
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, ImageBuffer, Pixel};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+type Buffer<P> = ImageBuffer<P, Vec<<P as Pixel>::Subpixel>>;
+
+/// How an image is brought to the target resolution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResizeMode {
+    /// Scale to fit into the target, keeping the aspect ratio. One side may
+    /// end up shorter than requested.
+    Fit,
+    /// Scale to cover the target, keeping the aspect ratio, and cut off what
+    /// exceeds it at both sides.
+    Fill,
+    /// Cut the center out of the image without scaling.
+    Crop,
+}
+
+impl FromStr for ResizeMode {
+    type Err = anyhow::Error;
+
+    fn from_str(mode: &str) -> Result<Self> {
+        match mode {
+            "fit" => Ok(ResizeMode::Fit),
+            "fill" => Ok(ResizeMode::Fill),
+            "crop" => Ok(ResizeMode::Crop),
+            _ => Err(Error::InvalidArgument(format!(
+                "Unknown resize mode \"{}\", expected fit, fill or crop",
+                mode
+            ))
+            .into()),
+        }
+    }
+}
+
+/// Resolution extracted images are brought to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Resize {
+    pub width: u32,
+    pub height: u32,
+    pub mode: ResizeMode,
+}
+
+impl Resize {
+    /// Parse a resolution given as `WIDTHxHEIGHT`, e.g. `1920x1080`.
+    pub fn parse(resolution: &str, mode: ResizeMode) -> Result<Self> {
+        let invalid = || {
+            Error::InvalidArgument(format!(
+                "Invalid resolution \"{}\", expected WIDTHxHEIGHT like 1920x1080",
+                resolution
+            ))
+        };
+        let (width, height) = resolution.trim().split_once('x').ok_or_else(invalid)?;
+        let width: u32 = width.parse().map_err(|_| invalid())?;
+        let height: u32 = height.parse().map_err(|_| invalid())?;
+        if width == 0 || height == 0 {
+            return Err(invalid().into());
+        }
+        Ok(Self {
+            width,
+            height,
+            mode,
+        })
+    }
+}
+
+pub fn resize(image: DynamicImage, target: &Resize) -> DynamicImage {
+    match image {
+        DynamicImage::ImageRgb16(image) => DynamicImage::ImageRgb16(resize_buffer(image, target)),
+        DynamicImage::ImageRgba16(image) => {
+            DynamicImage::ImageRgba16(resize_buffer(image, target))
+        }
+        image if image.color().has_alpha() => {
+            DynamicImage::ImageRgba8(resize_buffer(image.into_rgba8(), target))
+        }
+        image => DynamicImage::ImageRgb8(resize_buffer(image.into_rgb8(), target)),
+    }
+}
+
+fn resize_buffer<P: Pixel + 'static>(image: Buffer<P>, target: &Resize) -> Buffer<P> {
+    let (width, height) = image.dimensions();
+    if (width, height) == (target.width, target.height) {
+        return image;
+    }
+    match target.mode {
+        ResizeMode::Fit => imageops::resize(
+            &image,
+            // Keep the aspect ratio, the limiting side gets the target length
+            target.width.min(
+                ((width as u64 * target.height as u64) / height as u64).max(1) as u32,
+            ),
+            target.height.min(
+                ((height as u64 * target.width as u64) / width as u64).max(1) as u32,
+            ),
+            FilterType::Lanczos3,
+        ),
+        ResizeMode::Fill => {
+            let scale = f64::max(
+                target.width as f64 / width as f64,
+                target.height as f64 / height as f64,
+            );
+            let scaled_width = ((width as f64 * scale).round() as u32).max(target.width);
+            let scaled_height = ((height as f64 * scale).round() as u32).max(target.height);
+            let scaled = imageops::resize(&image, scaled_width, scaled_height, FilterType::Lanczos3);
+            crop_center(&scaled, target.width, target.height)
+        }
+        ResizeMode::Crop => crop_center(&image, target.width, target.height),
+    }
+}
+
+/// Center of the image, images smaller than the target are kept as they are
+/// along that side.
+fn crop_center<P: Pixel + 'static>(image: &Buffer<P>, width: u32, height: u32) -> Buffer<P> {
+    let width = width.min(image.width());
+    let height = height.min(image.height());
+    let x = (image.width() - width) / 2;
+    let y = (image.height() - height) / 2;
+    imageops::crop_imm(image, x, y, width, height).to_image()
+}
@@ -0,0 +1,213 @@
+// heic-to-dynamic-gnome-wallpaper
+// Copyright (C) 2022 Johannes Wünsche
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::util::time;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, TimeZone, Timelike, Utc};
+use std::f64::consts::PI;
+
+const MINUTES_PER_DAY: usize = 24 * 60;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Altitude of the sun in degrees above the horizon as seen from `location`
+/// at the given instant.
+///
+/// This uses the NOAA general solar position approximation which is accurate
+/// to a fraction of a degree, more than enough to time wallpaper frames.
+pub fn altitude(location: &Location, instant: DateTime<Utc>) -> f64 {
+    let hour = instant.hour() as f64
+        + instant.minute() as f64 / 60.0
+        + instant.second() as f64 / 3600.0;
+    // Fractional year in radians
+    let gamma = 2.0 * PI / 365.0 * (instant.ordinal0() as f64 + (hour - 12.0) / 24.0);
+    // Equation of time in minutes
+    let eqtime = 229.18
+        * (0.000075 + 0.001868 * gamma.cos()
+            - 0.032077 * gamma.sin()
+            - 0.014615 * (2.0 * gamma).cos()
+            - 0.040849 * (2.0 * gamma).sin());
+    // Declination in radians
+    let decl = 0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin()
+        - 0.006758 * (2.0 * gamma).cos()
+        + 0.000907 * (2.0 * gamma).sin()
+        - 0.002697 * (3.0 * gamma).cos()
+        + 0.00148 * (3.0 * gamma).sin();
+
+    let true_solar_minutes = hour * 60.0 + eqtime + 4.0 * location.longitude;
+    let hour_angle = (true_solar_minutes / 4.0 - 180.0).to_radians();
+    let lat = location.latitude.to_radians();
+
+    let cos_zenith =
+        (lat.sin() * decl.sin() + lat.cos() * decl.cos() * hour_angle.cos()).clamp(-1.0, 1.0);
+    90.0 - cos_zenith.acos().to_degrees()
+}
+
+/// The course of the sun over a single local day, sampled once per minute
+/// from 12 hours before to 12 hours after solar noon. Depending on the
+/// longitude and the time zone, this range may start on the previous or end
+/// on the next day.
+pub struct SunDay {
+    /// Minutes from local midnight to the first sample
+    start: i64,
+    altitudes: Vec<f64>,
+}
+
+impl SunDay {
+    pub fn new(location: &Location, date: NaiveDate) -> Self {
+        Self::starting_at(location, local_midnight(date))
+    }
+
+    /// Course of the sun around the solar noon of the day starting at
+    /// `midnight`.
+    fn starting_at(location: &Location, midnight: DateTime<Utc>) -> Self {
+        let sample = |start: i64| {
+            (0..MINUTES_PER_DAY as i64)
+                .map(|minute| altitude(location, midnight + Duration::minutes(start + minute)))
+                .collect()
+        };
+        let day = Self {
+            start: 0,
+            altitudes: sample(0),
+        };
+        let start = day.noon() as i64 - MINUTES_PER_DAY as i64 / 2;
+        Self {
+            start,
+            altitudes: sample(start),
+        }
+    }
+
+    pub fn max_altitude(&self) -> f64 {
+        self.altitudes[self.noon()]
+    }
+
+    pub fn min_altitude(&self) -> f64 {
+        self.altitudes.iter().copied().fold(f64::INFINITY, f64::min)
+    }
+
+    /// Sample at which the sun is at its highest.
+    fn noon(&self) -> usize {
+        self.altitudes
+            .iter()
+            .enumerate()
+            .fold(0, |max, (idx, alt)| if *alt > self.altitudes[max] { idx } else { max })
+    }
+
+    /// Fraction of the day at which the sun passes `altitude`, either while
+    /// rising in the morning or setting in the evening. Altitudes which are
+    /// not reached on this day are mapped to the closest extreme.
+    pub fn time_of_altitude(&self, altitude: f64, rising: bool) -> f32 {
+        let noon = self.noon();
+        let alts = &self.altitudes;
+        let lowest = |range: std::ops::Range<usize>| {
+            range.fold(None, |min: Option<usize>, idx| match min {
+                Some(min) if alts[min] <= alts[idx] => Some(min),
+                _ => Some(idx),
+            })
+        };
+
+        let minute = if rising {
+            let start = lowest(0..noon + 1).unwrap_or(noon);
+            (start..noon)
+                .find(|&idx| alts[idx] <= altitude && altitude <= alts[idx + 1])
+                .map(|idx| idx as f64 + (altitude - alts[idx]) / (alts[idx + 1] - alts[idx]).max(f64::EPSILON))
+                .unwrap_or(if altitude > alts[noon] { noon } else { start } as f64)
+        } else {
+            let end = lowest(noon..alts.len()).unwrap_or(noon);
+            (noon..end)
+                .find(|&idx| alts[idx] >= altitude && altitude >= alts[idx + 1])
+                .map(|idx| idx as f64 + (alts[idx] - altitude) / (alts[idx] - alts[idx + 1]).max(f64::EPSILON))
+                .unwrap_or(if altitude > alts[noon] { noon } else { end } as f64)
+        };
+        time::wrap_time_of_day(((minute + self.start as f64) / MINUTES_PER_DAY as f64) as f32)
+    }
+}
+
+fn local_midnight(date: NaiveDate) -> DateTime<Utc> {
+    let midnight = date.and_hms_opt(0, 0, 0).expect("Midnight is always a valid time");
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+        .unwrap_or_else(|| Utc.from_utc_datetime(&midnight))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Royal Observatory Greenwich, NOAA's solar calculator gives for
+    // 2024-06-21 a sunrise at 03:43, solar noon at 12:02 and sunset at 20:21
+    // UTC with the sun 61.96° above the horizon at noon.
+    const GREENWICH: Location = Location {
+        latitude: 51.4769,
+        longitude: 0.0,
+    };
+    // Altitude of the sun's center at sunrise and sunset, including refraction
+    const HORIZON: f64 = -0.833;
+
+    fn midsummer() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 6, 21, 0, 0, 0).unwrap()
+    }
+
+    fn minutes(hour: u32, minute: u32) -> f32 {
+        (hour * 60 + minute) as f32
+    }
+
+    #[test]
+    fn solar_noon() {
+        let day = SunDay::starting_at(&GREENWICH, midsummer());
+        let noon = (day.start + day.noon() as i64) as f32;
+        assert!((noon - minutes(12, 2)).abs() <= 2.0);
+        assert!((day.max_altitude() - 61.96).abs() < 0.2);
+    }
+
+    #[test]
+    fn sunrise_and_sunset() {
+        let day = SunDay::starting_at(&GREENWICH, midsummer());
+        let sunrise = day.time_of_altitude(HORIZON, true) * MINUTES_PER_DAY as f32;
+        let sunset = day.time_of_altitude(HORIZON, false) * MINUTES_PER_DAY as f32;
+        assert!((sunrise - minutes(3, 43)).abs() <= 2.0, "sunrise at {}", sunrise);
+        assert!((sunset - minutes(20, 21)).abs() <= 2.0, "sunset at {}", sunset);
+    }
+
+    // Tokyo is 9 hours ahead of UTC, NOAA gives for 2024-06-21 a sunrise at
+    // 19:25 UTC on the previous day and a sunset at 10:00 UTC.
+    #[test]
+    fn sunrise_before_midnight() {
+        let tokyo = Location {
+            latitude: 35.6895,
+            longitude: 139.6917,
+        };
+        let day = SunDay::starting_at(&tokyo, midsummer());
+        let rising = |altitude| day.time_of_altitude(altitude, true) * MINUTES_PER_DAY as f32;
+        let sunrise = rising(HORIZON);
+        let sunset = day.time_of_altitude(HORIZON, false) * MINUTES_PER_DAY as f32;
+        assert!((sunrise - minutes(19, 25)).abs() <= 2.0, "sunrise at {}", sunrise);
+        assert!((sunset - minutes(10, 0)).abs() <= 2.0, "sunset at {}", sunset);
+        // Dawn precedes the sunrise instead of being clamped to midnight
+        let dawn = rising(-10.0);
+        assert!(dawn > minutes(18, 0) && dawn < sunrise, "dawn at {}", dawn);
+    }
+
+    #[test]
+    fn altitude_at_noon() {
+        let noon = midsummer() + Duration::minutes(12 * 60 + 2);
+        assert!((altitude(&GREENWICH, noon) - 61.96).abs() < 0.2);
+    }
+}
@@ -13,14 +13,31 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
-pub fn to_rem_hours(secs: u16) -> u16 {
-    secs / 60 / 60
+pub fn to_rem_hours(secs: u32) -> u16 {
+    (secs / 60 / 60 % 24) as u16
 }
 
-pub fn to_rem_min(secs: u16) -> u16 {
-    secs / 60 % 60
+pub fn to_rem_min(secs: u32) -> u16 {
+    (secs / 60 % 60) as u16
 }
 
-pub fn to_rem_sec(secs: u16) -> u16 {
-    secs % 60
+pub fn to_rem_sec(secs: u32) -> u16 {
+    (secs % 60) as u16
+}
+
+/// Time of day, given as fraction of the day, formatted as HH:MM:SS.
+pub fn format_time_of_day(time: f32) -> String {
+    let secs = (time.rem_euclid(1.0) * crate::DAY_SECS).round() as u32 % 86400;
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Bring a time given as fraction of the day into `[0, 1)`. `rem_euclid`
+/// alone yields 1.0 for tiny negative values due to rounding.
+pub fn wrap_time_of_day(time: f32) -> f32 {
+    let time = time.rem_euclid(1.0);
+    if time >= 1.0 {
+        0.0
+    } else {
+        time
+    }
 }